@@ -0,0 +1,399 @@
+use crate::config::{AccessLogConfig, AccessLogFormat, LogRotation};
+use actix_web::{
+    dev::{BodySize, MessageBody, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, CONTENT_LENGTH, REFERER, USER_AGENT},
+    Error,
+};
+use chrono::{DateTime, Local, Timelike};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+    task::{Context, Poll},
+    thread,
+    time::Instant,
+};
+
+enum Output {
+    Stdout,
+    File {
+        path: PathBuf,
+        file: File,
+        size: u64,
+        opened: DateTime<Local>,
+    },
+}
+
+// Sends log lines to the writer thread, so requests don't wait for disk I/O
+#[derive(Clone)]
+pub struct LogSender {
+    format: AccessLogFormat,
+    sender: Sender<String>,
+}
+
+// Writes log lines to stdout or a file, rotating the file by size or time
+pub struct LogWriter {
+    output: Output,
+    max_size: Option<u64>,
+    rotation: Option<LogRotation>,
+    max_files: Option<usize>,
+}
+
+impl LogWriter {
+    // Opens the log and writes it on its own thread
+    pub fn spawn(config: &AccessLogConfig) -> Result<LogSender, String> {
+        let mut writer = Self::new(config)?;
+        let (sender, receiver) = channel::<String>();
+        thread::spawn(move || {
+            for line in receiver {
+                if let Err(err) = writer.write_line(&line) {
+                    eprintln!("{}", err);
+                }
+            }
+        });
+        Ok(LogSender {
+            format: config.format,
+            sender,
+        })
+    }
+
+    fn new(config: &AccessLogConfig) -> Result<Self, String> {
+        let output = match &config.file {
+            Some(path) => {
+                let (file, size) = open_log_file(path)?;
+                Output::File {
+                    path: path.clone(),
+                    file,
+                    size,
+                    opened: Local::now(),
+                }
+            }
+            None => Output::Stdout,
+        };
+        Ok(Self {
+            output,
+            max_size: config.max_size,
+            rotation: config.rotation,
+            max_files: config.max_files,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        if self.needs_rotation(line.len() as u64) {
+            self.rotate()?;
+        }
+        match &mut self.output {
+            Output::Stdout => {
+                let stdout = io::stdout();
+                let mut handle = stdout.lock();
+                writeln!(handle, "{}", line)
+                    .map_err(|err| format!("Failed to write access log: {}", err))
+            }
+            Output::File { file, size, .. } => {
+                writeln!(file, "{}", line)
+                    .map_err(|err| format!("Failed to write access log: {}", err))?;
+                *size += line.len() as u64 + 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn needs_rotation(&self, line_len: u64) -> bool {
+        let (size, opened) = match &self.output {
+            Output::File { size, opened, .. } => (*size, opened),
+            Output::Stdout => return false,
+        };
+        if let Some(max_size) = self.max_size {
+            if size > 0 && size + line_len + 1 > max_size {
+                return true;
+            }
+        }
+        let now = Local::now();
+        match self.rotation {
+            Some(LogRotation::Daily) => now.date_naive() != opened.date_naive(),
+            Some(LogRotation::Hourly) => {
+                now.date_naive() != opened.date_naive() || now.hour() != opened.hour()
+            }
+            None => false,
+        }
+    }
+
+    // Moves the current file to <file>.<timestamp> and starts a new one
+    fn rotate(&mut self) -> Result<(), String> {
+        let path = match &self.output {
+            Output::File { path, .. } => path.clone(),
+            Output::Stdout => return Ok(()),
+        };
+
+        let timestamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+        let mut rotated_path = PathBuf::from(format!("{}.{}", path.display(), timestamp));
+        let mut n = 1;
+        while rotated_path.exists() {
+            rotated_path = PathBuf::from(format!("{}.{}.{}", path.display(), timestamp, n));
+            n += 1;
+        }
+        fs::rename(&path, &rotated_path)
+            .map_err(|err| format!("Failed to rotate access log '{}': {}", path.display(), err))?;
+
+        let (file, size) = open_log_file(&path)?;
+        self.output = Output::File {
+            path: path.clone(),
+            file,
+            size,
+            opened: Local::now(),
+        };
+
+        if let Some(max_files) = self.max_files {
+            remove_old_files(&path, max_files)?;
+        }
+        Ok(())
+    }
+}
+
+fn open_log_file(path: &PathBuf) -> Result<(File, u64), String> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent).map_err(|err| {
+                format!(
+                    "Failed to create access log dir '{}': {}",
+                    parent.display(),
+                    err
+                )
+            })?;
+        }
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("Failed to open access log '{}': {}", path.display(), err))?;
+    let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+    Ok((file, size))
+}
+
+// Removes the oldest rotated files, the timestamps sort chronologically
+fn remove_old_files(path: &Path, max_files: usize) -> Result<(), String> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let prefix = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => format!("{}.", name),
+        None => return Ok(()),
+    };
+
+    let mut rotated = Vec::new();
+    let entries = fs::read_dir(&dir)
+        .map_err(|err| format!("Failed to read access log dir '{}': {}", dir.display(), err))?;
+    for entry in entries.flatten() {
+        if let Some(name) = entry.file_name().to_str() {
+            if name.starts_with(&prefix) {
+                rotated.push(entry.path());
+            }
+        }
+    }
+    rotated.sort();
+
+    if rotated.len() > max_files {
+        for old_path in &rotated[..rotated.len() - max_files] {
+            fs::remove_file(old_path).map_err(|err| {
+                format!(
+                    "Failed to remove old access log '{}': {}",
+                    old_path.display(),
+                    err
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+struct RequestInfo {
+    remote_addr: String,
+    time: DateTime<Local>,
+    method: String,
+    path: String,
+    version: String,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl RequestInfo {
+    fn new(req: &ServiceRequest) -> Self {
+        let header = |name: HeaderName| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        Self {
+            remote_addr: req
+                .peer_addr()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            time: Local::now(),
+            method: req.method().to_string(),
+            path: req
+                .uri()
+                .path_and_query()
+                .map(|path| path.as_str().to_string())
+                .unwrap_or_else(|| req.path().to_string()),
+            version: format!("{:?}", req.version()),
+            referer: header(REFERER),
+            user_agent: header(USER_AGENT),
+        }
+    }
+
+    fn format(
+        &self,
+        format: AccessLogFormat,
+        status: u16,
+        bytes: Option<u64>,
+        response_time_ms: f64,
+    ) -> String {
+        let bytes_str = match bytes {
+            Some(bytes) if bytes > 0 => bytes.to_string(),
+            _ => "-".to_string(),
+        };
+        let common = format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            self.remote_addr,
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            escape(&self.path),
+            self.version,
+            status,
+            bytes_str
+        );
+        let combined = || {
+            format!(
+                "{} \"{}\" \"{}\"",
+                common,
+                escape(self.referer.as_deref().unwrap_or("-")),
+                escape(self.user_agent.as_deref().unwrap_or("-"))
+            )
+        };
+        match format {
+            AccessLogFormat::Common => common,
+            AccessLogFormat::Combined => combined(),
+            AccessLogFormat::CombinedTime => format!("{} {:.3}", combined(), response_time_ms),
+            AccessLogFormat::Json => serde_json::json!({
+                "remote_addr": self.remote_addr,
+                "time": self.time.to_rfc3339(),
+                "method": self.method,
+                "path": self.path,
+                "version": self.version,
+                "status": status,
+                "bytes": bytes.unwrap_or(0),
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+                "response_time_ms": response_time_ms,
+            })
+            .to_string(),
+        }
+    }
+}
+
+// Escapes quotes, backslashes and control characters in quoted fields
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Logs every request with its status, size and response time, disabled without a writer
+pub struct AccessLog {
+    sender: Option<LogSender>,
+}
+
+impl AccessLog {
+    pub fn new(sender: Option<LogSender>) -> Self {
+        Self { sender }
+    }
+}
+
+impl<S, B> Transform<S> for AccessLog
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AccessLogMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AccessLogMiddleware {
+            service,
+            sender: self.sender.clone(),
+        })
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: S,
+    sender: Option<LogSender>,
+}
+
+impl<S, B> Service for AccessLogMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let sender = match &self.sender {
+            Some(sender) => sender.clone(),
+            None => return Box::pin(self.service.call(req)),
+        };
+        let info = RequestInfo::new(&req);
+        let start = Instant::now();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let result = fut.await;
+            let response_time_ms = start.elapsed().as_secs_f64() * 1000.0;
+            let (status, bytes) = match &result {
+                Ok(res) => (res.status().as_u16(), response_size(res)),
+                Err(err) => (err.as_response_error().status_code().as_u16(), None),
+            };
+            let line = info.format(sender.format, status, bytes, response_time_ms);
+            // The writer thread only stops with the process
+            let _ = sender.sender.send(line);
+            result
+        })
+    }
+}
+
+// Size of the response body, streamed bodies like files report it in the Content-Length header
+fn response_size<B: MessageBody>(res: &ServiceResponse<B>) -> Option<u64> {
+    match res.response().body().size() {
+        BodySize::Sized(size) => Some(size),
+        BodySize::None | BodySize::Empty => Some(0),
+        BodySize::Stream => res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok()),
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{
+    config::AcmeConfig,
+    tls::{certified_key, CertResolver},
+};
+use acme_lib::{
+    create_p384_key,
+    persist::{FilePersist, Persist, PersistKey, PersistKind},
+    Certificate, Directory, DirectoryUrl,
+};
+use std::{fs, path::PathBuf, sync::Arc, thread, time::Duration};
+
+const DEFAULT_RENEW_DAYS: i64 = 30;
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const VALIDATION_DELAY_MS: u64 = 5000;
+
+// Loads a stored certificate and keeps it renewed in the background
+pub fn spawn_acme(
+    acme: AcmeConfig,
+    challenge_dir: PathBuf,
+    resolver: Arc<CertResolver>,
+) -> Result<(), String> {
+    if acme.domains.is_empty() {
+        return Err("At least one domain is required for ACME!".to_string());
+    }
+    fs::create_dir_all(&acme.state_dir).map_err(|err| {
+        format!(
+            "Failed to create ACME state dir '{}': {}",
+            acme.state_dir.display(),
+            err
+        )
+    })?;
+
+    // Serve the stored certificate right away if there is one, the ACME server is only
+    // contacted in the background so startup doesn't depend on it
+    if let Some((cert_pem, key_pem)) = stored_certificate(&acme)? {
+        match certified_key(cert_pem.as_bytes(), key_pem.as_bytes()) {
+            Ok(key) => resolver.set(key),
+            Err(err) => eprintln!("Ignoring invalid stored ACME certificate: {}", err),
+        }
+    }
+
+    thread::spawn(move || loop {
+        let interval = match renew(&acme, &challenge_dir) {
+            Ok(Some(cert)) => match install_certificate(&cert, &resolver) {
+                Ok(()) => {
+                    println!("Installed new certificate for {}", acme.domains.join(", "));
+                    CHECK_INTERVAL
+                }
+                Err(err) => {
+                    eprintln!("Failed to install ACME certificate: {}", err);
+                    RETRY_INTERVAL
+                }
+            },
+            Ok(None) => CHECK_INTERVAL,
+            Err(err) => {
+                eprintln!("Failed to obtain ACME certificate: {}", err);
+                RETRY_INTERVAL
+            }
+        };
+        thread::sleep(interval);
+    });
+    Ok(())
+}
+
+fn directory(acme: &AcmeConfig) -> Result<Directory<FilePersist>, String> {
+    let persist = FilePersist::new(&acme.state_dir);
+    let url = match &acme.directory_url {
+        Some(url) => DirectoryUrl::Other(url.as_str()),
+        None => DirectoryUrl::LetsEncrypt,
+    };
+    Directory::from_url(persist, url).map_err(|err| format!("ACME directory error: {}", err))
+}
+
+// Reads the PEM certificate and key persisted by the account of the email
+fn stored_certificate(acme: &AcmeConfig) -> Result<Option<(String, String)>, String> {
+    let persist = FilePersist::new(&acme.state_dir);
+    let read = |kind: PersistKind| {
+        persist
+            .get(&PersistKey::new(&acme.email, kind, &acme.domains[0]))
+            .map(|value| value.and_then(|value| String::from_utf8(value).ok()))
+            .map_err(|err| format!("Failed to load stored certificate: {}", err))
+    };
+    match (
+        read(PersistKind::Certificate)?,
+        read(PersistKind::PrivateKey)?,
+    ) {
+        (Some(cert_pem), Some(key_pem)) => Ok(Some((cert_pem, key_pem))),
+        _ => Ok(None),
+    }
+}
+
+// Orders a new certificate if there is none or it expires soon
+fn renew(acme: &AcmeConfig, challenge_dir: &PathBuf) -> Result<Option<Certificate>, String> {
+    let account = directory(acme)?
+        .account(&acme.email)
+        .map_err(|err| format!("ACME account error: {}", err))?;
+
+    let renew_days = acme.renew_days.unwrap_or(DEFAULT_RENEW_DAYS);
+    if let Some(cert) = account
+        .certificate(&acme.domains[0])
+        .map_err(|err| format!("Failed to load stored certificate: {}", err))?
+    {
+        if cert.valid_days_left() > renew_days {
+            return Ok(None);
+        }
+    }
+
+    fs::create_dir_all(challenge_dir).map_err(|err| {
+        format!(
+            "Failed to create challenge dir '{}': {}",
+            challenge_dir.display(),
+            err
+        )
+    })?;
+
+    let alt_names: Vec<&str> = acme.domains[1..].iter().map(|d| d.as_str()).collect();
+    let mut order = account
+        .new_order(&acme.domains[0], &alt_names)
+        .map_err(|err| format!("Failed to create order: {}", err))?;
+
+    // Complete the HTTP-01 challenges, served by the plain HTTP listener
+    let csr_order = loop {
+        if let Some(csr_order) = order.confirm_validations() {
+            break csr_order;
+        }
+        let auths = order
+            .authorizations()
+            .map_err(|err| format!("Failed to get authorizations: {}", err))?;
+        for auth in &auths {
+            let challenge = auth.http_challenge();
+            let token_path = challenge_dir.join(challenge.http_token());
+            fs::write(&token_path, challenge.http_proof()).map_err(|err| {
+                format!(
+                    "Failed to write challenge '{}': {}",
+                    token_path.display(),
+                    err
+                )
+            })?;
+            let result = challenge.validate(VALIDATION_DELAY_MS);
+            let _ = fs::remove_file(&token_path);
+            result.map_err(|err| {
+                format!("Validation failed for '{}': {}", auth.domain_name(), err)
+            })?;
+        }
+        order
+            .refresh()
+            .map_err(|err| format!("Failed to refresh order: {}", err))?;
+    };
+
+    let cert_order = csr_order
+        .finalize_pkey(create_p384_key(), VALIDATION_DELAY_MS)
+        .map_err(|err| format!("Failed to finalize order: {}", err))?;
+    let cert = cert_order
+        .download_and_save_cert()
+        .map_err(|err| format!("Failed to download certificate: {}", err))?;
+    Ok(Some(cert))
+}
+
+fn install_certificate(cert: &Certificate, resolver: &CertResolver) -> Result<(), String> {
+    let key = certified_key(cert.certificate().as_bytes(), cert.private_key().as_bytes())?;
+    resolver.set(key);
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use crate::{
+    config::FeedConfig,
+    generator::{FeedItem, FeedLinks},
+};
+use chrono::NaiveDate;
+use quick_xml::{se::Serializer, Writer};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct AtomFeed {
+    xmlns: String,
+    #[serde(rename = "$unflatten=title")]
+    title: String,
+    #[serde(rename = "$unflatten=subtitle")]
+    subtitle: String,
+    #[serde(rename = "link")]
+    links: Vec<AtomLink>,
+    #[serde(rename = "$unflatten=id")]
+    id: String,
+    #[serde(rename = "$unflatten=updated")]
+    updated: String,
+    author: AtomAuthor,
+    #[serde(rename = "entry")]
+    entries: Vec<AtomEntry>,
+}
+
+#[derive(Serialize)]
+struct AtomLink {
+    href: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rel: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AtomAuthor {
+    #[serde(rename = "$unflatten=name")]
+    name: String,
+}
+
+#[derive(Serialize)]
+struct AtomCategory {
+    term: String,
+}
+
+#[derive(Serialize)]
+struct AtomContent {
+    #[serde(rename = "type")]
+    content_type: String,
+    #[serde(rename = "$value")]
+    value: String,
+}
+
+#[derive(Serialize)]
+struct AtomEntry {
+    #[serde(rename = "$unflatten=title")]
+    title: String,
+    link: AtomLink,
+    #[serde(rename = "$unflatten=id")]
+    id: String,
+    #[serde(rename = "$unflatten=updated")]
+    updated: String,
+    #[serde(rename = "$unflatten=published")]
+    published: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<AtomAuthor>,
+    #[serde(rename = "category")]
+    categories: Vec<AtomCategory>,
+    #[serde(rename = "$unflatten=summary", skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    content: AtomContent,
+}
+
+pub fn date_to_rfc3339(date: &NaiveDate) -> String {
+    date.format("%Y-%m-%dT00:00:00Z").to_string()
+}
+
+// Empty feeds are updated when their source dir changed
+pub fn generate_atom_xml(
+    feed_items: &[FeedItem],
+    feed_cfg: &FeedConfig,
+    links: &FeedLinks,
+    source_modified: &NaiveDate,
+) -> Result<String, String> {
+    let mut entries = Vec::new();
+    for feed_item in feed_items {
+        let link = links.item(feed_item);
+
+        entries.push(AtomEntry {
+            title: feed_item.meta.title.clone(),
+            link: AtomLink {
+                href: link.clone(),
+                rel: Some("alternate".to_string()),
+                content_type: Some("text/html".to_string()),
+            },
+            id: link,
+            updated: date_to_rfc3339(&feed_item.meta.date),
+            published: date_to_rfc3339(&feed_item.meta.date),
+            author: feed_item
+                .meta
+                .author
+                .clone()
+                .map(|name| AtomAuthor { name }),
+            categories: feed_item
+                .meta
+                .tags
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|term| AtomCategory { term })
+                .collect(),
+            summary: feed_item.meta.subtitle.clone(),
+            content: AtomContent {
+                content_type: "html".to_string(),
+                value: feed_item.content.clone(),
+            },
+        });
+    }
+
+    // The feed is updated when the newest item was published
+    let updated = match feed_items.iter().map(|item| item.meta.date).max() {
+        Some(date) => date_to_rfc3339(&date),
+        None => date_to_rfc3339(source_modified),
+    };
+
+    let feed = AtomFeed {
+        xmlns: "http://www.w3.org/2005/Atom".to_string(),
+        title: feed_cfg.title.clone(),
+        subtitle: feed_cfg.description.clone(),
+        links: vec![
+            AtomLink {
+                href: links.feed.clone(),
+                rel: Some("self".to_string()),
+                content_type: Some("application/atom+xml".to_string()),
+            },
+            AtomLink {
+                href: links.index.clone(),
+                rel: Some("alternate".to_string()),
+                content_type: Some("text/html".to_string()),
+            },
+        ],
+        id: links.index.clone(),
+        updated,
+        author: AtomAuthor {
+            name: feed_cfg
+                .author
+                .clone()
+                .unwrap_or_else(|| feed_cfg.title.clone()),
+        },
+        entries,
+    };
+
+    let mut buffer = Vec::new();
+    let writer = Writer::new_with_indent(&mut buffer, b' ', 2);
+    let mut ser = Serializer::with_root(writer, Some("feed"));
+    feed.serialize(&mut ser)
+        .map_err(|err| format!("XML Serialization error: {}", err))?;
+    let atom_feed_str = String::from_utf8(buffer)
+        .map_err(|err| format!("Feed string conversion error: {}", err))?;
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>{}",
+        atom_feed_str
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::{feed_config, feed_item};
+    use std::path::Path;
+
+    #[test]
+    fn generates_atom() {
+        let feed_cfg = feed_config("{title: Blog, description: Posts, source_dir: posts}");
+        let index_output = serde_yaml::from_str("{template: index.html, link: blog}").unwrap();
+        let links =
+            FeedLinks::new("https://example.com", &index_output, Path::new("atom.xml")).unwrap();
+        let mut first = feed_item(
+            "first",
+            "{title: First, date: 2021-03-04, content_type: md, tags: [rust]}",
+            "<p>A & B</p>",
+        );
+        first.link = Some("blog/first".to_string());
+        // Items without a page link to the index
+        let second = feed_item(
+            "second",
+            "{title: Second, date: 2021-03-01, content_type: md, author: Jane}",
+            "",
+        );
+        let source_modified = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+
+        let xml = generate_atom_xml(&[first, second], &feed_cfg, &links, &source_modified).unwrap();
+        for expected in [
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?><feed xmlns=\"http://www.w3.org/2005/Atom\">",
+            "<title>Blog</title><subtitle>Posts</subtitle>",
+            "<link href=\"https://example.com/atom.xml\" rel=\"self\" type=\"application/atom+xml\"/>",
+            "<id>https://example.com/blog</id><updated>2021-03-04T00:00:00Z</updated>",
+            "<author><name>Blog</name></author>",
+            "<id>https://example.com/blog/first</id>",
+            "<category term=\"rust\"/>",
+            // The html is escaped once
+            "<content type=\"html\">&lt;p&gt;A &amp; B&lt;/p&gt;</content>",
+            "<id>https://example.com/blog#second</id>",
+            "<published>2021-03-01T00:00:00Z</published><author><name>Jane</name></author>",
+        ] {
+            assert!(xml.contains(expected), "{} not in {}", expected, xml);
+        }
+
+        // Empty feeds are updated with their source dir
+        let xml = generate_atom_xml(&[], &feed_cfg, &links, &source_modified).unwrap();
+        assert!(xml.contains("<updated>2020-01-01T00:00:00Z</updated>"));
+        assert!(!xml.contains("<entry>"));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{
+    config::SiteConfig,
+    file_server::{is_template, resolve, routes, Route},
+    generator::check_sources,
+    link_checker::{check_links, link_exists},
+    state::Site,
+    template_engine::TemplateEngine,
+};
+use quick_xml::{events::Event, Reader};
+
+// Checks a site without serving it, returns the number of checked routes or all problems found
+pub fn check(config: &SiteConfig, drafts: bool) -> Result<usize, Vec<String>> {
+    let mut problems = Vec::new();
+
+    if let Err(err) = TemplateEngine::load(config) {
+        problems.push(err);
+    }
+    problems.extend(check_sources(config));
+    // The site can't be generated with broken templates or sources
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    let site = Site::load(config.clone(), drafts).map_err(|err| vec![err])?;
+
+    // Generated feeds must be well-formed XML
+    for path in site.generator.paths() {
+        if path.extension().is_some_and(|ext| ext == "xml") {
+            if let Some(content) = site.generator.get(path) {
+                if let Err(err) = check_xml(&content.body) {
+                    problems.push(format!("'{}': Invalid XML: {}", path.display(), err));
+                }
+            }
+        }
+    }
+
+    for (link, target) in &config.permalinks {
+        if !link_exists(&site, target) {
+            problems.push(format!(
+                "Permalink '{}': Target '{}' doesn't exist",
+                link, target
+            ));
+        }
+    }
+
+    let routes = match routes(&site) {
+        Ok(routes) => routes,
+        Err(err) => {
+            problems.push(format!("Failed to list routes: {}", err));
+            return Err(problems);
+        }
+    };
+    for route in &routes {
+        if let Route::Rendered(relative_path) = resolve(&site, route) {
+            // Templates are rendered with the context of their pages
+            if is_template(config, &relative_path) {
+                continue;
+            }
+            // Rendered pages are cached for the link check
+            if let Err(err) = site.render_page(&relative_path) {
+                problems.push(format!("'{}': {}", relative_path.display(), err));
+            }
+        }
+    }
+    if let Err(err) = site.search_index() {
+        problems.push(err);
+    }
+    match check_links(&site) {
+        Ok(link_problems) => problems.extend(link_problems),
+        Err(err) => problems.push(format!("Failed to check links: {}", err)),
+    }
+
+    if problems.is_empty() {
+        Ok(routes.len())
+    } else {
+        Err(problems)
+    }
+}
+
+fn check_xml(xml: &str) -> Result<(), String> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut depth = 0usize;
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(_)) => depth += 1,
+            Ok(Event::End(_)) => depth = depth.saturating_sub(1),
+            Ok(Event::Eof) if depth > 0 => return Err("Unclosed element".to_string()),
+            Ok(Event::Eof) => return Ok(()),
+            Ok(_) => {}
+            Err(err) => return Err(format!("{} (at byte {})", err, reader.buffer_position())),
+        }
+        buf.clear();
+    }
+}
@@ -0,0 +1,50 @@
+use clap::{Parser, Subcommand};
+use std::{net::IpAddr, path::PathBuf};
+
+#[derive(Parser)]
+#[clap(version, about = "A webserver for static sites with feeds")]
+pub struct Cli {
+    /// Path to the config file (default: <config dir>/webserver/config.yaml)
+    #[clap(long, short, global = true, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Override the root directory of the default site
+    #[clap(long, global = true, parse(from_os_str))]
+    pub root: Option<PathBuf>,
+    /// Override the address to listen on
+    #[clap(long, global = true)]
+    pub address: Option<IpAddr>,
+    /// Override the port to listen on
+    #[clap(long, short, global = true)]
+    pub port: Option<u16>,
+    /// Include drafts and scheduled posts
+    #[clap(long, global = true)]
+    pub drafts: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the server (default)
+    Serve,
+    /// Export the site as static files
+    Build {
+        /// Output directory
+        #[clap(long, short, default_value = "dist", parse(from_os_str))]
+        out: PathBuf,
+    },
+    /// Check the config, templates, feeds and links without running the server
+    Check,
+    /// Create a new post in a feed
+    NewPost {
+        /// Title of the post
+        title: String,
+        /// Title or source directory of the feed (default: the first feed)
+        #[clap(long, short)]
+        feed: Option<String>,
+        /// Host of the site (default: the default site)
+        #[clap(long, short)]
+        site: Option<String>,
+    },
+}
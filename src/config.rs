@@ -6,6 +6,82 @@ use serde::Deserialize;
 pub struct FeedOutput {
     pub template: PathBuf,
     pub link: PathBuf,
+    // Splits an index into pages at <link>/page/<n>
+    pub page_size: Option<usize>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TagOutput {
+    pub template: PathBuf,
+    // Tag pages are generated at <link>/<tag>
+    pub link: PathBuf,
+    // Generates an RSS feed at <link>/<tag>/<rss_feed_file>
+    pub rss_feed_file: Option<PathBuf>,
+    // Splits a tag page into pages at <link>/<tag>/page/<n>
+    pub page_size: Option<usize>,
+}
+
+// Markdown extension and render options, unset options are inherited
+#[derive(Deserialize, Clone, Default)]
+pub struct MarkdownConfig {
+    // Extensions
+    pub strikethrough: Option<bool>,
+    pub tagfilter: Option<bool>,
+    pub table: Option<bool>,
+    pub autolink: Option<bool>,
+    pub tasklist: Option<bool>,
+    pub superscript: Option<bool>,
+    // Prefix of the generated header ids, e.g. '' for '#title'
+    pub header_ids: Option<String>,
+    pub footnotes: Option<bool>,
+    pub description_lists: Option<bool>,
+    // Parse options
+    pub smart: Option<bool>,
+    pub default_info_string: Option<String>,
+    // Render options
+    pub hardbreaks: Option<bool>,
+    pub github_pre_lang: Option<bool>,
+    pub width: Option<usize>,
+    // Render raw html and dangerous links, only for trusted content, not per post or page
+    #[serde(rename = "unsafe")]
+    pub unsafe_: Option<bool>,
+    pub escape: Option<bool>,
+}
+
+impl MarkdownConfig {
+    // Options set in other override the options of self
+    pub fn merge(&self, other: &MarkdownConfig) -> MarkdownConfig {
+        MarkdownConfig {
+            strikethrough: other.strikethrough.or(self.strikethrough),
+            tagfilter: other.tagfilter.or(self.tagfilter),
+            table: other.table.or(self.table),
+            autolink: other.autolink.or(self.autolink),
+            tasklist: other.tasklist.or(self.tasklist),
+            superscript: other.superscript.or(self.superscript),
+            header_ids: other.header_ids.clone().or_else(|| self.header_ids.clone()),
+            footnotes: other.footnotes.or(self.footnotes),
+            description_lists: other.description_lists.or(self.description_lists),
+            smart: other.smart.or(self.smart),
+            default_info_string: other
+                .default_info_string
+                .clone()
+                .or_else(|| self.default_info_string.clone()),
+            hardbreaks: other.hardbreaks.or(self.hardbreaks),
+            github_pre_lang: other.github_pre_lang.or(self.github_pre_lang),
+            width: other.width.or(self.width),
+            unsafe_: other.unsafe_.or(self.unsafe_),
+            escape: other.escape.or(self.escape),
+        }
+    }
+
+    // Options of a post or page override the options of self, raw html can
+    // only be enabled by the site or feed config
+    pub fn merge_content(&self, other: &MarkdownConfig) -> Result<MarkdownConfig, String> {
+        if other.unsafe_ == Some(true) {
+            return Err("'unsafe' can only be enabled in the site or feed config".to_string());
+        }
+        Ok(self.merge(other))
+    }
 }
 
 #[derive(Deserialize, Clone)]
@@ -13,8 +89,12 @@ pub struct FeedConfig {
     // Feed properties
     pub title: String,
     pub description: String,
-    pub link: String,
+    pub author: Option<String>,
     pub rss_feed_link: Option<PathBuf>,
+    pub atom_feed_link: Option<PathBuf>,
+    pub json_feed_link: Option<PathBuf>,
+    // Maximum number of recent items in the RSS, Atom and JSON feeds
+    pub feed_item_limit: Option<usize>,
 
     // Source
     pub source_dir: PathBuf,
@@ -22,24 +102,199 @@ pub struct FeedConfig {
     pub content_output: Option<FeedOutput>,
     // Index file
     pub index_output: Option<FeedOutput>,
+    // Tag files
+    pub tag_output: Option<TagOutput>,
+    // Overrides the markdown options of the site
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
 }
 
 #[derive(Deserialize, Clone)]
-pub struct ServerConfig {
+pub struct AcmeConfig {
+    // Domains on the certificate, the first one is the primary domain
+    pub domains: Vec<String>,
+    pub email: String,
+    // Directory to store the account key and certificates
+    pub state_dir: PathBuf,
+    // ACME directory (default: Let's Encrypt)
+    pub directory_url: Option<String>,
+    // Renew the certificate when it expires within this many days (default: 30)
+    pub renew_days: Option<i64>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TlsConfig {
+    // Port of the HTTPS listener, the regular port redirects to it
+    pub port: u16,
+    // Certificate and key files, not required when using ACME
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub acme: Option<AcmeConfig>,
+    pub hsts_max_age: Option<u64>,
+    #[serde(default)]
+    pub hsts_include_subdomains: bool,
+    // Directory with ACME challenge files
+    // (default: <acme.state_dir>/acme-challenge or <root_dir>/.well-known/acme-challenge)
+    pub acme_challenge_dir: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    // Common Log Format
+    Common,
+    // Combined Log Format, Common with referer and user agent
+    #[default]
+    Combined,
+    // Combined followed by the response time in milliseconds, not readable by strict parsers
+    #[serde(rename = "combined_time")]
+    CombinedTime,
+    // One JSON object per line
+    Json,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    // Log file (default: stdout)
+    pub file: Option<PathBuf>,
+    // Rotate the log file when it exceeds this many bytes
+    pub max_size: Option<u64>,
+    // Rotate the log file every hour or day
+    pub rotation: Option<LogRotation>,
+    // Number of rotated log files to keep (default: all)
+    pub max_files: Option<usize>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MetricsConfig {
+    // Separate address and port of the /metrics endpoint, keep it private
     pub address: IpAddr,
     pub port: u16,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct LinkCheckConfig {
+    // Fail the build, and startup with 'serve', when there are broken links
+    #[serde(default)]
+    pub strict: bool,
+    // Also check the links when the server starts, reloads aren't checked
+    #[serde(default)]
+    pub serve: bool,
+    // Write the report to this file (default: stderr)
+    pub report_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct HeaderRule {
+    // Glob matched against the url path, e.g. '/assets/**' or '*.xml'
+    pub pattern: String,
+    pub cache_control: Option<String>,
+    // Additional response headers
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SitemapConfig {
+    // Sitemap file, becomes a sitemap index with <name>-<n>.xml parts when exceeding 50000 urls
+    pub link: PathBuf,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct RobotsConfig {
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub disallow: Vec<String>,
+}
+
+fn default_user_agent() -> String {
+    "*".to_string()
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SearchConfig {
+    // Url path of the search endpoint, e.g. 'search' for /search?q=
+    pub link: String,
+    // Maximum number of hits, also the maximum 'limit' of a query (default: 20)
+    pub max_results: Option<usize>,
+    // Writes the search index as JSON for client-side search when exporting
+    pub index_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SiteConfig {
+    // Host names matched against the Host header
+    #[serde(default)]
+    pub hosts: Vec<String>,
     pub server_name: String,
     pub root_dir: PathBuf,
     pub index: PathBuf,
     pub error_template: Option<PathBuf>,
     pub content_ext: String,
+    // Layout of markdown pages which don't set 'layout' in their front matter
+    pub page_layout: Option<PathBuf>,
+    // Dir of the layouts markdown pages can set in their front matter, they aren't pages
+    pub layout_dir: Option<PathBuf>,
     pub ignored_paths: Vec<PathBuf>,
     pub feeds: Vec<FeedConfig>,
-    pub permalinks: HashMap<String, String>
+    pub permalinks: HashMap<String, String>,
+    pub sitemap: Option<SitemapConfig>,
+    // Generates robots.txt when there is none in the root dir
+    pub robots: Option<RobotsConfig>,
+    pub search: Option<SearchConfig>,
+    // Markdown options of the site, overridable per feed and per post
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ServerConfig {
+    pub address: IpAddr,
+    pub port: u16,
+    pub tls: Option<TlsConfig>,
+    // Include drafts and scheduled posts (preview mode)
+    #[serde(default)]
+    pub drafts: bool,
+    // Seconds to wait for in-flight requests on SIGTERM or restart (default: 30)
+    pub shutdown_timeout: Option<u64>,
+    pub access_log: Option<AccessLogConfig>,
+    pub metrics: Option<MetricsConfig>,
+    // Check the links of all rendered pages when loading
+    pub link_check: Option<LinkCheckConfig>,
+    // Response headers by path pattern, applied in order
+    #[serde(default)]
+    pub headers: Vec<HeaderRule>,
+    // The default site, served when the host doesn't match any of the other sites
+    #[serde(flatten)]
+    pub site: SiteConfig,
+    // Additional sites
+    #[serde(default)]
+    pub sites: Vec<SiteConfig>,
+}
+
+// Overrides from the command line, applied on top of the config file
+#[derive(Clone, Default)]
+pub struct ConfigOverrides {
+    pub root_dir: Option<PathBuf>,
+    pub address: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub drafts: bool,
 }
 
 impl ServerConfig {
-    pub fn load(path: PathBuf) -> Result<Self, String> {
+    pub fn load(path: PathBuf, overrides: &ConfigOverrides) -> Result<Self, String> {
         let config_str = fs::read_to_string(&path).map_err(|err| {
             format!(
                 "Failed to read config file: {}\nPath: {}",
@@ -47,8 +302,55 @@ impl ServerConfig {
                 &path.display()
             )
         })?;
-        let config = serde_yaml::from_str::<ServerConfig>(&config_str)
+        let mut config = serde_yaml::from_str::<ServerConfig>(&config_str)
             .map_err(|err| format!("Failed to deserialize config file: {}", err))?;
+        if let Some(root_dir) = &overrides.root_dir {
+            config.site.root_dir = root_dir.clone();
+        }
+        if let Some(address) = overrides.address {
+            config.address = address;
+        }
+        if let Some(port) = overrides.port {
+            config.port = port;
+        }
+        if overrides.drafts {
+            config.drafts = true;
+        }
         Ok(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown(yaml: &str) -> MarkdownConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn merge_overrides_set_options() {
+        let site = markdown("table: true\nsmart: true\nunsafe: true");
+        let feed = markdown("smart: false\nheader_ids: ''");
+        let merged = site.merge(&feed);
+        assert_eq!(merged.table, Some(true));
+        assert_eq!(merged.smart, Some(false));
+        assert_eq!(merged.header_ids, Some(String::new()));
+        assert_eq!(merged.unsafe_, Some(true));
+        assert_eq!(merged.tasklist, None);
+    }
+
+    #[test]
+    fn content_cannot_set_unsafe() {
+        let site = markdown("unsafe: false");
+        assert!(site.merge_content(&markdown("unsafe: true")).is_err());
+        let merged = site.merge_content(&markdown("table: true")).unwrap();
+        assert_eq!(merged.unsafe_, Some(false));
+        assert_eq!(merged.table, Some(true));
+
+        // Content can still disable it
+        let site = markdown("unsafe: true");
+        let merged = site.merge_content(&markdown("unsafe: false")).unwrap();
+        assert_eq!(merged.unsafe_, Some(false));
+    }
+}
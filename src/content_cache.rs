@@ -0,0 +1,404 @@
+use actix_web::{
+    http::header::{
+        EntityTag, HttpDate, IfModifiedSince, IfNoneMatch, ACCEPT_ENCODING, CONTENT_ENCODING, ETAG,
+        LAST_MODIFIED, VARY,
+    },
+    web::Bytes,
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+// Content is compressed on the first request, so the fast levels are used
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW_SIZE: u32 = 22;
+// Maximum number of rendered pages kept in memory per site
+const MAX_CACHED_PAGES: usize = 4096;
+// Number of hex digits of the content hash in the ETag
+const ETAG_HASH_LEN: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    // Extension of precompressed files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+// Returns the encodings accepted by the client, in order of preference
+pub fn accepted_encodings(req: &HttpRequest) -> Vec<Encoding> {
+    match req
+        .headers()
+        .get(ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(header) => parse_accept_encoding(header),
+        None => Vec::new(),
+    }
+}
+
+// Encodings with a q-value above 0 by descending q-value, brotli wins ties
+// Encodings which aren't listed get the q-value of '*'
+fn parse_accept_encoding(header: &str) -> Vec<Encoding> {
+    let mut brotli = None;
+    let mut gzip = None;
+    let mut any = None;
+    for part in header.split(',') {
+        let mut params = part.split(';');
+        let name = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .filter_map(|q| q.trim().parse::<f32>().ok())
+            .next()
+            .unwrap_or(1.0);
+        match name.as_str() {
+            "br" => brotli = Some(quality),
+            "gzip" | "x-gzip" => gzip = Some(quality),
+            "*" => any = Some(quality),
+            _ => {}
+        }
+    }
+
+    let mut encodings: Vec<(Encoding, f32)> = [(Encoding::Brotli, brotli), (Encoding::Gzip, gzip)]
+        .iter()
+        .filter_map(|(encoding, quality)| quality.or(any).map(|quality| (*encoding, quality)))
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+    // The sort is stable, so brotli stays first on equal q-values
+    encodings.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    encodings
+        .into_iter()
+        .map(|(encoding, _)| encoding)
+        .collect()
+}
+
+// Content which is compressed once per encoding, when it is first requested
+pub struct CachedContent {
+    pub body: String,
+    gzip: OnceLock<Bytes>,
+    brotli: OnceLock<Bytes>,
+    // Hash of the body, used for the ETag
+    hash: String,
+    // Time of generation or rendering, in whole seconds like the Last-Modified header
+    // Unchanged content keeps the time of its previous version on reload
+    last_modified: SystemTime,
+    // Served with 'X-Robots-Tag: noindex', set by the front matter of markdown pages
+    pub noindex: bool,
+}
+
+impl CachedContent {
+    pub fn new(body: String) -> Self {
+        let mut hash = sha1_smol::Sha1::from(body.as_bytes()).digest().to_string();
+        hash.truncate(ETAG_HASH_LEN);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let last_modified = UNIX_EPOCH + Duration::from_secs(now.as_secs());
+
+        Self {
+            body,
+            gzip: OnceLock::new(),
+            brotli: OnceLock::new(),
+            hash,
+            last_modified,
+            noindex: false,
+        }
+    }
+
+    pub fn with_noindex(mut self, noindex: bool) -> Self {
+        self.noindex = noindex;
+        self
+    }
+
+    // Compressed body, compressing into a Vec can't fail
+    fn compressed(&self, encoding: Encoding) -> Bytes {
+        match encoding {
+            Encoding::Gzip => self
+                .gzip
+                .get_or_init(|| {
+                    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                    let _ = encoder.write_all(self.body.as_bytes());
+                    Bytes::from(encoder.finish().unwrap_or_default())
+                })
+                .clone(),
+            Encoding::Brotli => self
+                .brotli
+                .get_or_init(|| {
+                    let mut brotli = Vec::new();
+                    {
+                        let mut writer = brotli::CompressorWriter::new(
+                            &mut brotli,
+                            BROTLI_BUFFER_SIZE,
+                            BROTLI_QUALITY,
+                            BROTLI_WINDOW_SIZE,
+                        );
+                        let _ = writer.write_all(self.body.as_bytes());
+                    }
+                    Bytes::from(brotli)
+                })
+                .clone(),
+        }
+    }
+
+    pub fn same_content(&self, other: &CachedContent) -> bool {
+        self.hash == other.hash && self.noindex == other.noindex
+    }
+
+    // Strong ETag of a representation, which differs per encoding
+    fn etag(&self, encoding: Option<Encoding>) -> EntityTag {
+        match encoding {
+            Some(encoding) => EntityTag::strong(format!("{}-{}", self.hash, encoding.name())),
+            None => EntityTag::strong(self.hash.clone()),
+        }
+    }
+
+    // Whether the client's cached copy is still current, If-None-Match takes precedence
+    fn not_modified(&self, req: &HttpRequest, etag: &EntityTag) -> bool {
+        if let Some(if_none_match) = req.get_header::<IfNoneMatch>() {
+            return match if_none_match {
+                IfNoneMatch::Any => true,
+                IfNoneMatch::Items(items) => items.iter().any(|item| item.weak_eq(etag)),
+            };
+        }
+        match req.get_header::<IfModifiedSince>() {
+            Some(IfModifiedSince(since)) => SystemTime::from(since) >= self.last_modified,
+            None => false,
+        }
+    }
+
+    // Builds a response with the preferred encoding the client accepts,
+    // or a 304 if the client's cached copy is still current
+    pub fn response(&self, req: &HttpRequest, content_type: mime::Mime) -> HttpResponse {
+        let encoding = accepted_encodings(req).first().copied();
+        let etag = self.etag(encoding);
+        let last_modified = HttpDate::from(self.last_modified);
+
+        if self.not_modified(req, &etag) {
+            return HttpResponse::NotModified()
+                .set_header(ETAG, etag.to_string())
+                .set_header(LAST_MODIFIED, last_modified)
+                .set_header(VARY, "Accept-Encoding")
+                .finish();
+        }
+
+        let mut response = HttpResponse::Ok();
+        response
+            .content_type(content_type.to_string())
+            .set_header(ETAG, etag.to_string())
+            .set_header(LAST_MODIFIED, last_modified)
+            .set_header(VARY, "Accept-Encoding");
+        match encoding {
+            Some(encoding) => response
+                .set_header(CONTENT_ENCODING, encoding.name())
+                .body(self.compressed(encoding)),
+            None => response.body(self.body.clone()),
+        }
+    }
+}
+
+// Rendered pages of a site, cleared when the site is reloaded
+pub struct RenderCache {
+    pages: Mutex<HashMap<PathBuf, Arc<CachedContent>>>,
+    // Pages of the previous version of the site, reused if they render the same
+    previous: Mutex<HashMap<PathBuf, Arc<CachedContent>>>,
+}
+
+impl RenderCache {
+    pub fn new() -> Self {
+        Self {
+            pages: Mutex::new(HashMap::new()),
+            previous: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Keeps the previous version of unchanged pages, so Last-Modified only changes with the content
+    pub fn keep_unchanged(&self, previous: &RenderCache) {
+        let mut previous_pages = previous
+            .previous
+            .lock()
+            .expect("Render cache lock is poisoned!")
+            .clone();
+        previous_pages.extend(
+            previous
+                .pages
+                .lock()
+                .expect("Render cache lock is poisoned!")
+                .iter()
+                .map(|(path, content)| (path.clone(), content.clone())),
+        );
+        // Pages of removed paths would pile up over reloads otherwise
+        evict(&mut previous_pages, 0);
+        for (path, content) in self
+            .pages
+            .lock()
+            .expect("Render cache lock is poisoned!")
+            .iter_mut()
+        {
+            keep_previous(content, previous_pages.get(path));
+        }
+        *self
+            .previous
+            .lock()
+            .expect("Render cache lock is poisoned!") = previous_pages;
+    }
+
+    pub fn get_or_render<F>(&self, path: &PathBuf, render: F) -> Result<Arc<CachedContent>, String>
+    where
+        F: FnOnce() -> Result<CachedContent, String>,
+    {
+        if let Some(content) = self
+            .pages
+            .lock()
+            .expect("Render cache lock is poisoned!")
+            .get(path)
+        {
+            return Ok(content.clone());
+        }
+        // Render without holding the lock
+        let mut content = Arc::new(render()?);
+        keep_previous(
+            &mut content,
+            self.previous
+                .lock()
+                .expect("Render cache lock is poisoned!")
+                .get(path),
+        );
+        let mut pages = self.pages.lock().expect("Render cache lock is poisoned!");
+        evict(&mut pages, 1);
+        pages.insert(path.clone(), content.clone());
+        Ok(content)
+    }
+}
+
+// Removes arbitrary pages until there is room for the new ones
+fn evict(pages: &mut HashMap<PathBuf, Arc<CachedContent>>, new_pages: usize) {
+    while pages.len() + new_pages > MAX_CACHED_PAGES {
+        let path = match pages.keys().next() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        pages.remove(&path);
+    }
+}
+
+// Replaces content with its previous version if it didn't change
+pub fn keep_previous(content: &mut Arc<CachedContent>, previous: Option<&Arc<CachedContent>>) {
+    if let Some(previous) = previous {
+        if previous.same_content(content) {
+            *content = previous.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_encoding_quality() {
+        assert_eq!(
+            parse_accept_encoding("gzip, deflate, br"),
+            vec![Encoding::Brotli, Encoding::Gzip]
+        );
+        assert_eq!(
+            parse_accept_encoding("gzip;q=1, br;q=0.1"),
+            vec![Encoding::Gzip, Encoding::Brotli]
+        );
+        assert_eq!(parse_accept_encoding("gzip, br;q=0"), vec![Encoding::Gzip]);
+        assert_eq!(
+            parse_accept_encoding("*;q=0.5, br;q=0"),
+            vec![Encoding::Gzip]
+        );
+        assert_eq!(parse_accept_encoding("identity"), vec![]);
+    }
+
+    #[test]
+    fn compresses_lazily() {
+        let content = CachedContent::new("hello".repeat(100));
+        assert!(content.gzip.get().is_none());
+        let gzip = content.compressed(Encoding::Gzip);
+        let mut decoder = flate2::read::GzDecoder::new(&gzip[..]);
+        let mut body = String::new();
+        std::io::Read::read_to_string(&mut decoder, &mut body).unwrap();
+        assert_eq!(body, content.body);
+        assert!(content.brotli.get().is_none());
+    }
+
+    #[test]
+    fn evicts_pages_over_limit() {
+        let mut pages = HashMap::new();
+        for i in 0..MAX_CACHED_PAGES + 10 {
+            pages.insert(
+                PathBuf::from(i.to_string()),
+                Arc::new(CachedContent::new(String::new())),
+            );
+        }
+        evict(&mut pages, 1);
+        assert_eq!(pages.len(), MAX_CACHED_PAGES - 1);
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        let content = CachedContent::new("hello".to_string());
+        assert_eq!(content.hash, "aaf4c61ddcc5e8a2");
+        assert_eq!(
+            content.etag(Some(Encoding::Gzip)).tag(),
+            "aaf4c61ddcc5e8a2-gzip"
+        );
+    }
+
+    #[test]
+    fn keeps_previous_version_of_unchanged_content() {
+        let previous = Arc::new(CachedContent::new("hello".to_string()));
+        let mut content = Arc::new(CachedContent::new("hello".to_string()));
+        keep_previous(&mut content, Some(&previous));
+        assert!(Arc::ptr_eq(&content, &previous));
+
+        let mut changed = Arc::new(CachedContent::new("changed".to_string()));
+        keep_previous(&mut changed, Some(&previous));
+        assert!(!Arc::ptr_eq(&changed, &previous));
+
+        let mut noindex = Arc::new(CachedContent::new("hello".to_string()).with_noindex(true));
+        keep_previous(&mut noindex, Some(&previous));
+        assert!(!Arc::ptr_eq(&noindex, &previous));
+    }
+
+    #[test]
+    fn render_cache_keeps_unchanged_pages() {
+        let path = PathBuf::from("index.html");
+        let previous = RenderCache::new();
+        let first = previous
+            .get_or_render(&path, || Ok(CachedContent::new("page".to_string())))
+            .unwrap();
+
+        let cache = RenderCache::new();
+        cache.keep_unchanged(&previous);
+        let second = cache
+            .get_or_render(&path, || Ok(CachedContent::new("page".to_string())))
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+}
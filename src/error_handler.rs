@@ -1,4 +1,4 @@
-use crate::{config::ServerConfig, template_engine::TemplateEngine};
+use crate::state::SharedState;
 use actix_http::Response;
 use actix_web::{
     dev::{Body, ServiceResponse},
@@ -34,23 +34,13 @@ fn get_error_response<B>(res: &ServiceResponse<B>, status: &StatusCode) -> Respo
 
     let mut error_ctx = Context::new();
     error_ctx.insert("status_code", &status.as_str());
-    error_ctx.insert(
-        "reason",
-        status.canonical_reason().unwrap_or_else(|| "Unknown"),
-    );
+    error_ctx.insert("reason", status.canonical_reason().unwrap_or("Unknown"));
 
-    let template_engine = request
-        .app_data::<Data<TemplateEngine>>()
-        .map(|t| t.get_ref());
-    let config = request
-        .app_data::<Data<ServerConfig>>()
-        .map(|t| t.get_ref());
-
-    if let (Some(template_engine), Some(config)) = (template_engine, config) {
-        if let Some(error_template) = config.error_template.clone() {
-            if let Ok(content) =
-                template_engine.render_file(error_template, &error_ctx)
-            {
+    if let Some(state) = request.app_data::<Data<SharedState>>() {
+        let state = state.get();
+        let site = state.site(request.connection_info().host());
+        if let Some(error_template) = site.config.error_template.clone() {
+            if let Ok(content) = site.template_engine.render_file(error_template, &error_ctx) {
                 return Response::build(res.status())
                     .content_type("text/html")
                     .body(content);
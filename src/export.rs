@@ -0,0 +1,197 @@
+use crate::{
+    config::SiteConfig,
+    file_server::{is_template, resolve, routes, Route},
+    state::Site,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// File name used for routes without an extension
+const EXPORT_INDEX_FILE: &str = "index.html";
+// Output dir of the default site if it has no hosts
+const DEFAULT_SITE_DIR: &str = "default";
+
+// Output dirs of the sites, the default site comes first
+// A single site is exported to the output dir itself, multiple sites are exported
+// side by side to subdirectories named after their first host
+pub fn out_dirs(sites: &[&SiteConfig], out_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if sites.len() == 1 {
+        return Ok(vec![out_dir.to_path_buf()]);
+    }
+    let mut dirs = Vec::new();
+    for (i, config) in sites.iter().enumerate() {
+        let name = match config.hosts.first() {
+            Some(host) => host.to_ascii_lowercase(),
+            None if i == 0 => DEFAULT_SITE_DIR.to_string(),
+            None => {
+                return Err(format!(
+                    "Site '{}' has no hosts to name its output dir!",
+                    config.server_name
+                ))
+            }
+        };
+        let dir = out_dir.join(name);
+        if dirs.contains(&dir) {
+            return Err(format!(
+                "Site '{}' has the same output dir '{}' as another site!",
+                config.server_name,
+                dir.display()
+            ));
+        }
+        dirs.push(dir);
+    }
+    Ok(dirs)
+}
+
+// Writes every route to the output directory
+pub fn export(site: &Site, out_dir: &Path) -> Result<usize, String> {
+    let mut routes = routes(site)?;
+    // Templates are rendered with the context of their pages, the error template by the server
+    routes.retain(|route| match resolve(site, route) {
+        Route::Rendered(relative_path) => !is_template(&site.config, &relative_path),
+        _ => true,
+    });
+    for route in &routes {
+        let uri_path = PathBuf::from(route);
+        let out_path = if uri_path.extension().is_some() {
+            out_dir.join(&uri_path)
+        } else {
+            out_dir.join(&uri_path).join(EXPORT_INDEX_FILE)
+        };
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create dir '{}': {}", parent.display(), err))?;
+        }
+
+        match resolve(site, route) {
+            Route::Generated(path) => {
+                if let Some(content) = site.generator.get(&path) {
+                    write_file(&out_path, &content.body)?;
+                }
+            }
+            Route::Rendered(relative_path) => {
+                let content = site.render_page(&relative_path)?;
+                write_file(&out_path, &content.body)?;
+            }
+            Route::StaticFile(abs_path) => {
+                fs::copy(&abs_path, &out_path).map_err(|err| {
+                    format!(
+                        "Failed to copy '{}' to '{}': {}",
+                        abs_path.display(),
+                        out_path.display(),
+                        err
+                    )
+                })?;
+            }
+            Route::Redirect(_) | Route::Search | Route::NotFound => {}
+        }
+    }
+
+    // Static search index for client-side search
+    if let (Some(search_cfg), Some(search_index)) = (&site.config.search, site.search_index()?) {
+        if let Some(index_file) = &search_cfg.index_file {
+            write_file(&out_dir.join(index_file), &search_index.to_json()?)?;
+        }
+    }
+
+    if !site.config.permalinks.is_empty() {
+        eprintln!(
+            "Warning: {} permalink(s) are not exported, they require a server redirect!",
+            site.config.permalinks.len()
+        );
+    }
+    Ok(routes.len())
+}
+
+fn write_file(path: &PathBuf, content: &str) -> Result<(), String> {
+    fs::write(path, content)
+        .map_err(|err| format!("Failed to write file '{}': {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::{feed_config, post, root_dir, write_files};
+
+    fn site_config(hosts: &[&str]) -> SiteConfig {
+        let mut config = crate::test_site::site_config(Path::new("www"));
+        config.hosts = hosts.iter().map(|host| host.to_string()).collect();
+        config
+    }
+
+    #[test]
+    fn single_site_is_exported_to_out_dir() {
+        let config = site_config(&[]);
+        let out = PathBuf::from("out");
+        assert_eq!(out_dirs(&[&config], &out).unwrap(), vec![out]);
+    }
+
+    #[test]
+    fn multiple_sites_are_exported_side_by_side() {
+        let default = site_config(&[]);
+        let blog = site_config(&["Blog.example.com", "www.blog.example.com"]);
+        let out = PathBuf::from("out");
+        assert_eq!(
+            out_dirs(&[&default, &blog], &out).unwrap(),
+            vec![out.join("default"), out.join("blog.example.com")]
+        );
+    }
+
+    #[test]
+    fn other_site_without_hosts_fails() {
+        let default = site_config(&["example.com"]);
+        let other = site_config(&[]);
+        assert!(out_dirs(&[&default, &other], &PathBuf::from("out")).is_err());
+    }
+
+    #[test]
+    fn duplicate_out_dirs_fail() {
+        let default = site_config(&["example.com"]);
+        let other = site_config(&["example.com"]);
+        assert!(out_dirs(&[&default, &other], &PathBuf::from("out")).is_err());
+    }
+
+    #[test]
+    fn exports_site_with_feed() {
+        let root_dir = root_dir("export-feed");
+        write_files(
+            &root_dir,
+            &[
+                ("index.html", "Home"),
+                ("error.html", "Error {{ status_code }}"),
+                (
+                    "templates/post.html",
+                    "<h1>{{ meta.title }}</h1>{{ content }}",
+                ),
+                (
+                    "templates/blog.html",
+                    "{% for item in items %}{{ item.meta.title }}{% endfor %}",
+                ),
+                ("posts/first.md", &post("First", "2021-03-04", "")),
+            ],
+        );
+        let mut config = crate::test_site::site_config(&root_dir);
+        config.error_template = Some(PathBuf::from("error.html"));
+        config.feeds = vec![feed_config(
+            "title: Blog
+description: Posts
+source_dir: posts
+content_output: {template: templates/post.html, link: blog}
+index_output: {template: templates/blog.html, link: blog}",
+        )];
+        let site = Site::load(config, false).unwrap();
+
+        let out = root_dir.join("dist");
+        // The post source is served as a static file too
+        assert_eq!(export(&site, &out).unwrap(), 4);
+        let read = |path: &str| fs::read_to_string(out.join(path)).unwrap();
+        assert_eq!(read("index.html"), "Home");
+        assert_eq!(read("blog/index.html"), "First");
+        assert!(read("blog/first/index.html").starts_with("<h1>First</h1>"));
+        assert!(!out.join("templates").exists());
+        assert!(!out.join("error").exists());
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+}
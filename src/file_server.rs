@@ -1,95 +1,367 @@
-use crate::{config::ServerConfig, generator::Generator, template_engine::TemplateEngine};
+use crate::{
+    config::SiteConfig,
+    content_cache::accepted_encodings,
+    metrics::{self, RouteKind},
+    page::{is_layout, is_markdown_page, MARKDOWN_EXT},
+    robots::set_noindex_header,
+    search::SearchQuery,
+    state::{SharedState, Site},
+};
 use actix_files::{file_extension_to_mime, NamedFile};
-use actix_web::{http::header::ContentType, web, HttpRequest, HttpResponse};
-use std::{ffi::OsString, path::PathBuf};
-use tera::Context;
-
-pub async fn files(
-    req: HttpRequest,
-    config: web::Data<ServerConfig>,
-    template_engine: web::Data<TemplateEngine>,
-    generator: web::Data<Generator>,
-) -> HttpResponse {
-    let uri_path_str = &(req.uri().path().to_string())[1..];
+use actix_web::{
+    http::header::{HeaderValue, CONTENT_ENCODING, VARY},
+    web, HttpRequest, HttpResponse,
+};
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Route {
+    // Redirect to another url
+    Redirect(String),
+    // Generated file from the generator
+    Generated(PathBuf),
+    // Static file on disk (absolute path)
+    StaticFile(PathBuf),
+    // Template or markdown page relative to the root dir
+    Rendered(PathBuf),
+    // Search endpoint
+    Search,
+    NotFound,
+}
+
+// Resolves an url path (without leading slash) to a route
+pub fn resolve(site: &Site, uri_path_str: &str) -> Route {
+    let config = &site.config;
     let uri_path = PathBuf::from(uri_path_str);
 
     // Check if file doesn't start with an ignored path
     for dir in &config.ignored_paths {
         if uri_path.starts_with(dir) {
-            return HttpResponse::NotFound().finish();
+            return Route::NotFound;
         }
     }
 
     // Permalinks redirect
     if let Some(link) = config.permalinks.get(uri_path_str) {
-        return HttpResponse::Found()
-            .set_header("Location", link.to_string())
-            .finish();
+        return Route::Redirect(link.to_string());
+    }
+
+    if let Some(search_cfg) = &config.search {
+        if uri_path_str == search_cfg.link {
+            return Route::Search;
+        }
     }
 
     // Check if url is a generated template
-    if let Some(content) = generator.get(&uri_path) {
-        let mime_type: mime::Mime = {
-            if let Some(ext) = uri_path.extension() {
-                if let Some(ext_str) = ext.to_str() {
-                    file_extension_to_mime(&ext_str)
-                } else {
-                    mime::TEXT_PLAIN
-                }
-            } else {
-                mime::TEXT_HTML
-            }
-        };
-        return HttpResponse::Ok().set(ContentType(mime_type)).body(content);
+    if site.generator.get(&uri_path).is_some() {
+        return Route::Generated(uri_path);
     }
 
     let abs_path = config.root_dir.join(uri_path.clone());
 
     // If url has an extension
     if let Some(ext) = uri_path.extension() {
-        // Redirect if url has content extension
-        if ext == OsString::from(&config.content_ext) {
+        // Redirect if url has content extension or is a markdown page
+        if ext == OsString::from(&config.content_ext) || is_markdown_page(config, &uri_path) {
             let clean_url = uri_path.with_extension("");
-            let url_string = format!("/{}", clean_url.display());
-            return HttpResponse::Found()
-                .set_header("Location", url_string)
-                .finish();
+            return Route::Redirect(format!("/{}", clean_url.display()));
         }
         // Regular file
-        if let Some(file) = NamedFile::open(abs_path).ok() {
-            if let Ok(response) = file.into_response(&req) {
-                return response;
-            } else {
-                return HttpResponse::BadRequest().finish();
-            }
+        if abs_path.is_file() {
+            return Route::StaticFile(abs_path);
         }
     }
     // If url has no extension
     else {
         // Return rendered file if exists
-        if let Some(relative_path) = {
-            let mut result = None;
-            let content_file = abs_path.with_extension(&config.content_ext);
-            if content_file.exists() {
-                result = Some(uri_path.with_extension(&config.content_ext))
-            } else if abs_path.is_dir() {
-                let index_path = abs_path.join(&config.index);
-                if index_path.exists() {
-                    result = Some(uri_path.join(&config.index))
+        let content_file = abs_path.with_extension(&config.content_ext);
+        let markdown_path = uri_path.with_extension(MARKDOWN_EXT);
+        if content_file.exists() {
+            return Route::Rendered(uri_path.with_extension(&config.content_ext));
+        } else if is_markdown_page(config, &markdown_path) {
+            return Route::Rendered(markdown_path);
+        } else if abs_path.is_dir() {
+            let index_path = abs_path.join(&config.index);
+            let markdown_index = uri_path.join(&config.index).with_extension(MARKDOWN_EXT);
+            if index_path.exists() {
+                return Route::Rendered(uri_path.join(&config.index));
+            } else if is_markdown_page(config, &markdown_index) {
+                return Route::Rendered(markdown_index);
+            }
+        } else if abs_path.is_file() {
+            // Files without extension, e.g. CNAME or _redirects
+            return Route::StaticFile(abs_path);
+        }
+    }
+
+    Route::NotFound
+}
+
+// Lists the url paths of all content files, static files and generated files
+pub fn routes(site: &Site) -> Result<Vec<String>, String> {
+    let config = &site.config;
+    let mut files = Vec::new();
+    list_files(&config.root_dir, &mut files)?;
+
+    let mut routes = Vec::new();
+    for file in files {
+        let relative_path = file
+            .strip_prefix(&config.root_dir)
+            .map_err(|err| err.to_string())?;
+        if let Some(ext) = relative_path.extension() {
+            if ext == OsString::from(&config.content_ext) || is_markdown_page(config, relative_path)
+            {
+                // Index files are served at their directory
+                let file_name = relative_path.with_extension(&config.content_ext);
+                if file_name.file_name() == Some(config.index.as_os_str()) {
+                    if let Some(parent) = relative_path.parent() {
+                        routes.push(parent.to_string_lossy().to_string());
+                    }
+                } else {
+                    let clean_url = relative_path.with_extension("");
+                    routes.push(clean_url.to_string_lossy().to_string());
                 }
+            } else {
+                routes.push(relative_path.to_string_lossy().to_string());
             }
-            result
-        } {
-            return match template_engine.render_file(relative_path, &Context::new()) {
-                Ok(content) => HttpResponse::Ok().content_type("text/html").body(content),
-                Err(err) => {
-                    eprintln!("Error while rendering file!\n{}", err);
-                    HttpResponse::InternalServerError().finish()
+        } else {
+            routes.push(relative_path.to_string_lossy().to_string());
+        }
+    }
+    for path in site.generator.paths() {
+        routes.push(path.to_string_lossy().to_string());
+    }
+
+    // Only keep routes that are actually served
+    routes.sort();
+    routes.dedup();
+    routes.retain(|route| !matches!(resolve(site, route), Route::Redirect(_) | Route::NotFound));
+    Ok(routes)
+}
+
+fn list_files(dir: &PathBuf, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    for entry in fs::read_dir(dir)
+        .map_err(|err| format!("Failed to read dir '{}': {}", dir.display(), err))?
+    {
+        let path = entry.map_err(|err| err.to_string())?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Whether a content file is a template of the site instead of a page
+pub fn is_template(config: &SiteConfig, relative_path: &PathBuf) -> bool {
+    if config.error_template.as_ref() == Some(relative_path)
+        || config.page_layout.as_ref() == Some(relative_path)
+        || is_layout(config, relative_path)
+    {
+        return true;
+    }
+    config.feeds.iter().any(|feed_cfg| {
+        let templates = [
+            feed_cfg
+                .content_output
+                .as_ref()
+                .map(|output| &output.template),
+            feed_cfg
+                .index_output
+                .as_ref()
+                .map(|output| &output.template),
+            feed_cfg.tag_output.as_ref().map(|output| &output.template),
+        ];
+        templates.contains(&Some(relative_path))
+    })
+}
+
+// Returns the mime type of a generated file
+pub fn generated_mime(path: &Path) -> mime::Mime {
+    if let Some(ext) = path.extension() {
+        if let Some(ext_str) = ext.to_str() {
+            file_extension_to_mime(ext_str)
+        } else {
+            mime::TEXT_PLAIN
+        }
+    } else {
+        mime::TEXT_HTML
+    }
+}
+
+pub async fn files(req: HttpRequest, state: web::Data<SharedState>) -> HttpResponse {
+    let state = state.get();
+    let site = state.site(req.connection_info().host());
+    let uri_path_str = &(req.uri().path().to_string())[1..];
+
+    let route = resolve(site, uri_path_str);
+    let route_kind = match &route {
+        Route::Redirect(_) => RouteKind::Redirect,
+        Route::Generated(_) => RouteKind::Generated,
+        Route::StaticFile(_) => RouteKind::StaticFile,
+        Route::Rendered(_) => RouteKind::Rendered,
+        Route::Search => RouteKind::Search,
+        Route::NotFound => RouteKind::NotFound,
+    };
+    req.extensions_mut().insert(route_kind);
+
+    match route {
+        Route::Redirect(link) => HttpResponse::Found().set_header("Location", link).finish(),
+        Route::Generated(path) => match site.generator.get(&path) {
+            Some(content) => {
+                let mut response = content.response(&req, generated_mime(&path));
+                if site.generator.is_noindex(&path) {
+                    set_noindex_header(&mut response);
+                }
+                response
+            }
+            None => HttpResponse::NotFound().finish(),
+        },
+        Route::StaticFile(abs_path) => static_file(&req, abs_path),
+        Route::Search => search(&req, site),
+        Route::Rendered(relative_path) => match site.render_page(&relative_path) {
+            Ok(content) => {
+                let mut response = content.response(&req, mime::TEXT_HTML);
+                if content.noindex {
+                    set_noindex_header(&mut response);
                 }
-            };
+                response
+            }
+            Err(err) => {
+                metrics::template_render_error();
+                eprintln!(
+                    "Error while rendering file '{}'!\n{}",
+                    relative_path.display(),
+                    err
+                );
+                HttpResponse::InternalServerError().finish()
+            }
+        },
+        // Return a 404 if nothing found
+        Route::NotFound => HttpResponse::NotFound().finish(),
+    }
+}
+
+// Returns the search hits of the query as JSON
+fn search(req: &HttpRequest, site: &Site) -> HttpResponse {
+    let search_index = match site.search_index() {
+        Ok(Some(search_index)) => search_index,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(err) => {
+            eprintln!("{}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    match web::Query::<SearchQuery>::from_query(req.query_string()) {
+        Ok(query) => HttpResponse::Ok().json(serde_json::json!({
+            "query": query.q,
+            "hits": search_index.search(&query.q, query.limit),
+        })),
+        Err(_) => HttpResponse::BadRequest().body("Missing query parameter 'q'"),
+    }
+}
+
+// Serves a static file, or a precompressed sibling (<file>.br, <file>.gz) if the client accepts it
+fn static_file(req: &HttpRequest, abs_path: PathBuf) -> HttpResponse {
+    for encoding in accepted_encodings(req) {
+        let mut compressed_path = abs_path.clone().into_os_string();
+        compressed_path.push(".");
+        compressed_path.push(encoding.extension());
+        let compressed_path = PathBuf::from(compressed_path);
+        if !compressed_path.is_file() {
+            continue;
         }
+        // Content type and disposition are derived from the original path
+        let file = match fs::File::open(&compressed_path)
+            .and_then(|file| NamedFile::from_file(file, &abs_path))
+        {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        return match file.into_response(req) {
+            Ok(mut response) => {
+                let headers = response.headers_mut();
+                headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+                headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+                response
+            }
+            Err(_) => HttpResponse::BadRequest().finish(),
+        };
     }
 
-    // Return a 404 if nothing found
-    HttpResponse::NotFound().finish()
+    match NamedFile::open(abs_path) {
+        Ok(file) => match file.into_response(req) {
+            Ok(response) => response,
+            Err(_) => HttpResponse::BadRequest().finish(),
+        },
+        Err(_) => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::{root_dir, site_config, write_files};
+
+    fn site() -> Site {
+        let root_dir = root_dir("file-server");
+        write_files(
+            &root_dir,
+            &[
+                ("index.html", "Home"),
+                ("about.html", "About"),
+                ("notes.md", "# Notes"),
+                ("docs/index.html", "Docs"),
+                ("assets/style.css", "body {}"),
+                ("CNAME", "example.com"),
+                ("private/secret.txt", "Secret"),
+            ],
+        );
+        let mut config = site_config(&root_dir);
+        config.ignored_paths = vec![PathBuf::from("private")];
+        config
+            .permalinks
+            .insert("old".to_string(), "/about".to_string());
+        Site::load(config, false).unwrap()
+    }
+
+    #[test]
+    fn resolves_and_lists_routes() {
+        let site = site();
+        let root_dir = site.config.root_dir.clone();
+        assert_eq!(
+            resolve(&site, "about"),
+            Route::Rendered(PathBuf::from("about.html"))
+        );
+        assert_eq!(
+            resolve(&site, "about.html"),
+            Route::Redirect("/about".to_string())
+        );
+        assert_eq!(
+            resolve(&site, "notes"),
+            Route::Rendered(PathBuf::from("notes.md"))
+        );
+        assert_eq!(
+            resolve(&site, "docs"),
+            Route::Rendered(PathBuf::from("docs/index.html"))
+        );
+        assert_eq!(resolve(&site, "old"), Route::Redirect("/about".to_string()));
+        assert_eq!(
+            resolve(&site, "CNAME"),
+            Route::StaticFile(root_dir.join("CNAME"))
+        );
+        assert_eq!(resolve(&site, "private/secret.txt"), Route::NotFound);
+        assert_eq!(resolve(&site, "missing"), Route::NotFound);
+
+        assert_eq!(
+            routes(&site).unwrap(),
+            vec!["", "CNAME", "about", "assets/style.css", "docs", "notes"]
+        );
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
 }
@@ -0,0 +1,193 @@
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+const YAML_DELIMITER: &str = "---";
+const TOML_DELIMITER: &str = "+++";
+const LEGACY_SEPARATOR: &str = "___";
+
+#[derive(Clone, Copy)]
+pub enum FrontMatterFormat {
+    Yaml,
+    Toml,
+}
+
+pub struct FrontMatter<'a> {
+    pub format: FrontMatterFormat,
+    pub meta: &'a str,
+    pub body: &'a str,
+    // Number of lines in the file before the meta starts
+    line_offset: usize,
+}
+
+// Splits a file into front matter and body
+// Supports '---' (YAML) and '+++' (TOML) delimited front matter at the start of the file
+// and the legacy format with YAML meta followed by a '___' separator
+pub fn split(input: &str) -> Option<FrontMatter<'_>> {
+    let input = input.trim_start_matches('\u{feff}');
+    if let Some((meta, body)) = split_delimited(input, YAML_DELIMITER) {
+        return Some(FrontMatter {
+            format: FrontMatterFormat::Yaml,
+            meta,
+            body,
+            line_offset: 1,
+        });
+    }
+    if let Some((meta, body)) = split_delimited(input, TOML_DELIMITER) {
+        return Some(FrontMatter {
+            format: FrontMatterFormat::Toml,
+            meta,
+            body,
+            line_offset: 1,
+        });
+    }
+
+    // Legacy format, the separator must be on its own line so '___' in the body isn't matched
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        if line.trim_end() == LEGACY_SEPARATOR {
+            return Some(FrontMatter {
+                format: FrontMatterFormat::Yaml,
+                meta: &input[..offset],
+                body: &input[offset + line.len()..],
+                line_offset: 0,
+            });
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn split_delimited<'a>(input: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let rest = input.strip_prefix(delimiter)?;
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+impl<'a> FrontMatter<'a> {
+    // Deserializes the meta, errors point to the line and column in the file
+    pub fn parse<T: DeserializeOwned>(&self, path: &Path) -> Result<T, String> {
+        let (message, location) = match self.format {
+            FrontMatterFormat::Yaml => match serde_yaml::from_str::<T>(self.meta) {
+                Ok(meta) => return Ok(meta),
+                Err(err) => (
+                    err.to_string(),
+                    err.location().map(|loc| (loc.line(), loc.column())),
+                ),
+            },
+            FrontMatterFormat::Toml => match toml::from_str::<T>(self.meta) {
+                Ok(meta) => return Ok(meta),
+                // The location of TOML errors is 0-based
+                Err(err) => (
+                    err.to_string(),
+                    err.line_col().map(|(line, col)| (line + 1, col + 1)),
+                ),
+            },
+        };
+
+        match location {
+            Some((line, column)) => {
+                // Strip the location relative to the meta from the message
+                let message = match message.rfind(" at line ") {
+                    Some(i) => &message[..i],
+                    None => &message,
+                };
+                Err(format!(
+                    "{}:{}:{}: {}",
+                    path.display(),
+                    line + self.line_offset,
+                    column,
+                    message
+                ))
+            }
+            None => Err(format!("{}: {}", path.display(), message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::FeedMeta;
+    use chrono::NaiveDate;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Meta {
+        title: String,
+    }
+
+    #[test]
+    fn splits_yaml() {
+        let front_matter = split("---\ntitle: Hello\n---\nBody\n").unwrap();
+        assert!(matches!(front_matter.format, FrontMatterFormat::Yaml));
+        assert_eq!(front_matter.meta, "title: Hello\n");
+        assert_eq!(front_matter.body, "Body\n");
+    }
+
+    #[test]
+    fn splits_toml_with_crlf_and_bom() {
+        let front_matter = split("\u{feff}+++\r\ntitle = \"Hello\"\r\n+++\r\nBody").unwrap();
+        assert!(matches!(front_matter.format, FrontMatterFormat::Toml));
+        assert_eq!(front_matter.meta, "title = \"Hello\"\r\n");
+        assert_eq!(front_matter.body, "Body");
+    }
+
+    #[test]
+    fn splits_legacy_separator_on_its_own_line() {
+        let front_matter = split("title: Hello\n___\nBody with ___ inside\n___\n").unwrap();
+        assert_eq!(front_matter.meta, "title: Hello\n");
+        assert_eq!(front_matter.body, "Body with ___ inside\n___\n");
+    }
+
+    #[test]
+    fn ignores_separator_inside_text() {
+        assert!(split("Some text with a___b in it\n").is_none());
+        assert!(split("No front matter\n").is_none());
+    }
+
+    #[test]
+    fn unclosed_delimiter_is_not_front_matter() {
+        assert!(split("---\ntitle: Hello\nBody\n").is_none());
+    }
+
+    #[test]
+    fn parse_errors_point_to_the_file_line() {
+        let front_matter = split("---\ntitle: Hello\nfoo: [\n---\nBody").unwrap();
+        let err = front_matter
+            .parse::<Meta>(Path::new("post.md"))
+            .err()
+            .unwrap();
+        assert!(err.starts_with("post.md:4:"), "{}", err);
+    }
+
+    #[test]
+    fn parses_meta() {
+        let front_matter = split("+++\ntitle = \"Hello\"\n+++\n").unwrap();
+        let meta: Meta = front_matter.parse(Path::new("post.md")).unwrap();
+        assert_eq!(meta.title, "Hello");
+    }
+
+    #[test]
+    fn parses_toml_dates() {
+        let date = NaiveDate::from_ymd_opt(2021, 3, 4).unwrap();
+        for value in ["2021-03-04", "2021-03-04T10:00:00Z", "\"2021-03-04\""] {
+            let input = format!(
+                "+++\ntitle = \"Hello\"\ndate = {}\ncontent_type = \"md\"\n+++\n",
+                value
+            );
+            let front_matter = split(&input).unwrap();
+            let meta: FeedMeta = front_matter.parse(Path::new("post.md")).unwrap();
+            assert_eq!(meta.date, date);
+        }
+    }
+}
@@ -1,20 +1,37 @@
-use crate::{config::ServerConfig, rss::generate_rss_xml, template_engine::TemplateEngine};
-use chrono::NaiveDate;
+use crate::{
+    atom::generate_atom_xml,
+    config::{FeedConfig, FeedOutput, MarkdownConfig, SiteConfig},
+    content_cache::{keep_previous, CachedContent},
+    front_matter,
+    json_feed::generate_json_feed,
+    metrics,
+    rss::generate_rss_xml,
+    search::{strip_html, SearchDocument},
+    template_engine::TemplateEngine,
+};
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use comrak::{markdown_to_html, ComrakOptions};
 use serde::{self, Deserialize, Serialize};
-use std::{collections::HashMap, fs, path::PathBuf};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, UNIX_EPOCH},
+};
 use tera::Context;
 
 #[derive(Serialize, Deserialize, Clone)]
-struct FeedLink {
-    content: String,
-    url: String,
+pub struct FeedLink {
+    pub content: String,
+    pub url: String,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
-struct FeedImage {
-    alt: String,
-    file_name: String,
+pub struct FeedImage {
+    pub alt: String,
+    pub file_name: String,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -28,14 +45,23 @@ enum FeedContentType {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FeedMeta {
     pub title: String,
-    subtitle: Option<String>,
+    pub subtitle: Option<String>,
+    pub author: Option<String>,
     #[serde(with = "date_format")]
     pub date: NaiveDate,
     date_label: Option<String>,
-    tags: Option<Vec<String>>,
-    image: Option<FeedImage>,
-    links: Option<Vec<FeedLink>>,
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub draft: bool,
+    // Excluded from the sitemap and served with 'X-Robots-Tag: noindex'
+    #[serde(default)]
+    pub noindex: bool,
+    pub image: Option<FeedImage>,
+    pub links: Option<Vec<FeedLink>>,
     content_type: FeedContentType,
+    // Overrides the markdown options of the feed
+    #[serde(default, skip_serializing)]
+    markdown: MarkdownConfig,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -57,11 +83,51 @@ impl FeedItem {
     }
 }
 
+// Absolute links of a syndication feed, shared by the RSS, Atom and JSON feeds
+pub struct FeedLinks<'a> {
+    server_name: &'a str,
+    pub feed: String,
+    pub index: String,
+}
+
+impl<'a> FeedLinks<'a> {
+    pub fn new(
+        server_name: &'a str,
+        index_output: &FeedOutput,
+        feed_link: &Path,
+    ) -> Result<Self, String> {
+        let index_link = index_output
+            .link
+            .to_str()
+            .ok_or("Index link string conversion failed!")?;
+        let feed_link = feed_link
+            .to_str()
+            .ok_or("Feed link string conversion failed!")?;
+        Ok(Self {
+            server_name,
+            feed: format!("{}/{}", server_name, feed_link),
+            index: format!("{}/{}", server_name, index_link),
+        })
+    }
+
+    // Link of the item page, or of the item on the index page
+    pub fn item(&self, feed_item: &FeedItem) -> String {
+        match &feed_item.link {
+            Some(item_link) => format!("{}/{}", self.server_name, item_link),
+            None => format!("{}#{}", self.index, feed_item.file_name),
+        }
+    }
+
+    pub fn absolute(&self, path: &str) -> String {
+        format!("{}/{}", self.server_name, path.trim_start_matches('/'))
+    }
+}
+
 mod date_format {
     use chrono::NaiveDate;
     use serde::{self, Deserialize, Deserializer, Serializer};
 
-    const FORMAT: &'static str = "%Y-%m-%d";
+    const FORMAT: &str = "%Y-%m-%d";
 
     pub fn serialize<S>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -75,89 +141,328 @@ mod date_format {
     where
         D: Deserializer<'de>,
     {
-        let s = String::deserialize(deserializer)?;
-        let dt = NaiveDate::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)?;
+        let s = match Date::deserialize(deserializer)? {
+            Date::String(s) => s,
+            Date::Toml(datetime) => datetime.to_string(),
+        };
+        // Only the date of a TOML datetime is used
+        let date = s.split(['T', ' ']).next().unwrap_or_default();
+        let dt = NaiveDate::parse_from_str(date, FORMAT).map_err(serde::de::Error::custom)?;
         Ok(dt)
     }
+
+    // TOML front matter can have unquoted dates
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Date {
+        String(String),
+        Toml(toml::value::Datetime),
+    }
+}
+
+pub fn comrak_options(markdown: &MarkdownConfig) -> ComrakOptions {
+    let mut options = ComrakOptions::default();
+    let extension = &mut options.extension;
+    extension.strikethrough = markdown.strikethrough.unwrap_or(false);
+    extension.tagfilter = markdown.tagfilter.unwrap_or(false);
+    extension.table = markdown.table.unwrap_or(false);
+    extension.autolink = markdown.autolink.unwrap_or(false);
+    extension.tasklist = markdown.tasklist.unwrap_or(false);
+    extension.superscript = markdown.superscript.unwrap_or(false);
+    extension.header_ids = markdown.header_ids.clone();
+    extension.footnotes = markdown.footnotes.unwrap_or(false);
+    extension.description_lists = markdown.description_lists.unwrap_or(false);
+
+    options.parse.smart = markdown.smart.unwrap_or(false);
+    options.parse.default_info_string = markdown.default_info_string.clone();
+
+    let render = &mut options.render;
+    render.hardbreaks = markdown.hardbreaks.unwrap_or(false);
+    render.github_pre_lang = markdown.github_pre_lang.unwrap_or(false);
+    render.width = markdown.width.unwrap_or(0);
+    render.unsafe_ = markdown.unsafe_.unwrap_or(false);
+    render.escape = markdown.escape.unwrap_or(false);
+    options
 }
 
-fn render_content(input: &str, content_type: &FeedContentType) -> Result<String, String> {
-    let md_options = ComrakOptions::default();
+fn render_content(
+    input: &str,
+    content_type: &FeedContentType,
+    markdown: &MarkdownConfig,
+) -> Result<String, String> {
+    let md_options = comrak_options(markdown);
 
     match content_type {
         FeedContentType::Html => Ok(input.to_string()),
         FeedContentType::Markdown => {
-            let rendered = markdown_to_html(&input, &md_options);
+            let rendered = markdown_to_html(input, &md_options);
             Ok(rendered)
         }
     }
 }
 
+#[derive(Serialize, Clone)]
+struct FeedTag {
+    pub name: String,
+    pub count: usize,
+    pub link: Option<PathBuf>,
+}
+
 #[derive(Serialize)]
 struct FeedIndex {
+    // The tag of a tag page
+    pub tag: Option<String>,
     pub feed_link: Option<PathBuf>,
+    pub atom_feed_link: Option<PathBuf>,
+    pub json_feed_link: Option<PathBuf>,
     pub items: Vec<FeedItem>,
+    pub tags: Vec<FeedTag>,
+    // Pagination
+    pub current_page: usize,
+    pub total_pages: usize,
+    pub prev_link: Option<PathBuf>,
+    pub next_link: Option<PathBuf>,
+}
+
+// Link to a page of a paginated index, the first page is the index itself
+fn page_link(link: &Path, page: usize) -> PathBuf {
+    if page <= 1 {
+        link.to_path_buf()
+    } else {
+        link.join("page").join(page.to_string())
+    }
+}
+
+// Renders an index template, split into pages if a page size is set
+fn render_index_pages(
+    template_engine: &mut TemplateEngine,
+    template: &str,
+    template_path: &Path,
+    link: &Path,
+    page_size: Option<usize>,
+    mut index: FeedIndex,
+    files: &mut HashMap<PathBuf, String>,
+) -> Result<(), String> {
+    let items = std::mem::take(&mut index.items);
+    let pages: Vec<Vec<FeedItem>> = match page_size {
+        Some(0) => {
+            return Err(format!(
+                "Page size of '{}' must be larger than 0!",
+                link.display()
+            ))
+        }
+        Some(page_size) if !items.is_empty() => items
+            .chunks(page_size)
+            .map(|chunk| chunk.to_vec())
+            .collect(),
+        _ => vec![items],
+    };
+
+    index.total_pages = pages.len();
+    for (i, page_items) in pages.into_iter().enumerate() {
+        let page = i + 1;
+        index.current_page = page;
+        index.prev_link = if page > 1 {
+            Some(page_link(link, page - 1))
+        } else {
+            None
+        };
+        index.next_link = if page < index.total_pages {
+            Some(page_link(link, page + 1))
+        } else {
+            None
+        };
+        index.items = page_items;
+
+        let ctx = Context::from_serialize(&index).map_err(|err| err.to_string())?;
+        let content = template_engine
+            .render_string(template, &ctx)
+            .map_err(|err| {
+                format!(
+                    "Failed to render index template '{}': {}",
+                    template_path.display(),
+                    err
+                )
+            })?;
+        files.insert(page_link(link, page), content);
+    }
+    Ok(())
+}
+
+// Converts a title or tag to a string which can be used in urls and file names
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+// Slugs of the tags of a feed, used as the links of the tag pages. Tags with the
+// same slug get a number appended in order, tags without any usable character are
+// skipped
+fn tag_slugs<'a>(
+    tags: impl Iterator<Item = &'a String>,
+    feed_title: &str,
+) -> HashMap<String, String> {
+    let mut slugs = HashMap::new();
+    let mut used = HashSet::new();
+    for tag in tags {
+        let base = slugify(tag);
+        if base.is_empty() {
+            eprintln!(
+                "Skipping the tag page of '{}' in '{}', it has no characters usable in a link",
+                tag, feed_title
+            );
+            continue;
+        }
+        let mut slug = base.clone();
+        let mut n = 2;
+        while !used.insert(slug.clone()) {
+            slug = format!("{}-{}", base, n);
+            n += 1;
+        }
+        slugs.insert(tag.clone(), slug);
+    }
+    slugs
 }
 
 #[derive(Clone)]
 pub struct Generator {
-    files: HashMap<PathBuf, String>,
+    files: HashMap<PathBuf, Arc<CachedContent>>,
+    last_modified: HashMap<PathBuf, NaiveDate>,
+    // Content pages of feed items with noindex
+    noindex: HashSet<PathBuf>,
+    // Feed items for the search index
+    search_documents: Vec<SearchDocument>,
+    // Date of the first scheduled post that is not published yet
+    next_scheduled: Option<NaiveDate>,
+    // Number of published items by feed title
+    feed_counts: Vec<(String, usize)>,
+}
+
+// Markdown source files of a feed
+fn source_files(config: &SiteConfig, feed_cfg: &FeedConfig) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    let source_dir = config.root_dir.join(&feed_cfg.source_dir);
+    if source_dir.is_dir() {
+        for file in fs::read_dir(&source_dir).map_err(|err| err.to_string())? {
+            let file = file.map_err(|err| err.to_string())?;
+            let path = file.path();
+            if let Some(ext) = path.extension() {
+                if ext == "md" {
+                    paths.push(path);
+                }
+            }
+        }
+    }
+    Ok(paths)
+}
+
+// Date files were last added to or removed from the source dir of a feed
+fn source_modified(config: &SiteConfig, feed_cfg: &FeedConfig) -> NaiveDate {
+    let modified = fs::metadata(config.root_dir.join(&feed_cfg.source_dir))
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(UNIX_EPOCH);
+    DateTime::<Utc>::from(modified).date_naive()
+}
+
+fn read_feed_item(path: &PathBuf, markdown: &MarkdownConfig) -> Result<FeedItem, String> {
+    let file_str = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read '{}': {}", path.display(), err))?;
+    if let Some(front_matter) = front_matter::split(&file_str) {
+        let meta = front_matter.parse::<FeedMeta>(path)?;
+        let markdown = markdown
+            .merge_content(&meta.markdown)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let html = render_content(front_matter.body, &meta.content_type, &markdown)?;
+
+        if let Some(file_name) = &path.file_stem() {
+            Ok(FeedItem::new(
+                file_name.to_string_lossy().to_string(),
+                meta,
+                html,
+            ))
+        } else {
+            Err(format!(
+                "Failed to get file stem from path: {}",
+                path.display()
+            ))
+        }
+    } else {
+        Err(format!(
+            "Invalid syntax in '{}'. Start the file with '---' (YAML) or '+++' (TOML) front matter!",
+            path.display()
+        ))
+    }
+}
+
+// Reads every feed source file, returns all problems instead of stopping at the first one
+pub fn check_sources(config: &SiteConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    for feed_cfg in &config.feeds {
+        match source_files(config, feed_cfg) {
+            Ok(paths) => {
+                let markdown = config.markdown.merge(&feed_cfg.markdown);
+                for path in paths {
+                    if let Err(err) = read_feed_item(&path, &markdown) {
+                        problems.push(err);
+                    }
+                }
+            }
+            Err(err) => problems.push(format!(
+                "Failed to read source dir of feed '{}': {}",
+                feed_cfg.title, err
+            )),
+        }
+    }
+    problems
 }
 
 impl Generator {
     pub fn generate(
-        config: &ServerConfig,
+        config: &SiteConfig,
+        drafts: bool,
         template_engine: &mut TemplateEngine,
     ) -> Result<Self, String> {
-        let mut files = HashMap::new();
+        let start = Instant::now();
+        let mut all_files = HashMap::new();
+        let mut next_scheduled: Option<NaiveDate> = None;
+        let mut last_modified = HashMap::new();
+        let mut noindex = HashSet::new();
+        let mut search_documents = Vec::new();
+        let mut feed_counts = Vec::new();
+        let today = Local::now().date_naive();
         for feed_cfg in &config.feeds {
+            // Files generated for this feed
+            let mut files = HashMap::new();
             let mut feed_items = Vec::<FeedItem>::new();
+            let markdown = config.markdown.merge(&feed_cfg.markdown);
+            for path in source_files(config, feed_cfg)? {
+                feed_items.push(read_feed_item(&path, &markdown)?);
+            }
 
-            let source_dir = config.root_dir.join(&feed_cfg.source_dir);
-            if source_dir.is_dir() {
-                for file in fs::read_dir(&source_dir).map_err(|err| err.to_string())? {
-                    let file = file.map_err(|err| err.to_string())?;
-                    let path = file.path();
-                    if let Some(ext) = path.extension() {
-                        if ext.to_string_lossy().to_string() == "md" {
-                            let file_str =
-                                fs::read_to_string(&path).map_err(|err| err.to_string())?;
-                            if let Some((meta, content)) = file_str.split_once("___") {
-                                let meta =
-                                    serde_yaml::from_str::<FeedMeta>(&meta).map_err(|err| {
-                                        format!(
-                                            "Failed to deserialize file '{}': {}",
-                                            path.display(),
-                                            err.to_string(),
-                                        )
-                                    })?;
-                                let html = render_content(content, &meta.content_type)?;
-
-                                if let Some(file_name) = &path.file_stem() {
-                                    feed_items.push(FeedItem::new(
-                                        file_name.to_string_lossy().to_string(),
-                                        meta,
-                                        html,
-                                    ));
-                                } else {
-                                    return Err(format!(
-                                        "Failed to get file stem from path: {}",
-                                        path.display()
-                                    ));
-                                }
-                            } else {
-                                return Err(format!(
-                                    "Invalid syntax in '{}'. Make sure there is exactly one meta seperator!",
-                                    path.display()
-                                ));
-                            }
-                        }
+            // Drafts and scheduled posts are only included in preview mode
+            if !drafts {
+                for feed_item in &feed_items {
+                    if !feed_item.meta.draft && feed_item.meta.date > today {
+                        next_scheduled = Some(match next_scheduled {
+                            Some(date) => date.min(feed_item.meta.date),
+                            None => feed_item.meta.date,
+                        });
                     }
                 }
+                feed_items.retain(|item| !item.meta.draft && item.meta.date <= today);
             }
 
             // Sort the feed ascending by date
-            feed_items.sort_by(|a, b| b.meta.date.cmp(&a.meta.date));
+            feed_items.sort_by_key(|item| Reverse(item.meta.date));
+            feed_counts.push((feed_cfg.title.clone(), feed_items.len()));
+            let newest_date = feed_items.first().map(|item| item.meta.date);
 
             // Generate content
             if let Some(content_output) = &feed_cfg.content_output {
@@ -166,7 +471,7 @@ impl Generator {
                     format!(
                         "Failed to load template file '{}': {}",
                         &template_path.display(),
-                        err.to_string()
+                        err
                     )
                 })?;
                 for feed_item in &mut feed_items {
@@ -178,15 +483,66 @@ impl Generator {
                             format!(
                                 "Failed to render content template '{}': {}",
                                 &content_output.template.display(),
-                                err.to_string()
+                                err
                             )
                         })?;
                     let link = content_output.link.join(feed_item.file_name.clone());
                     feed_item.link = Some(link.to_string_lossy().to_string());
+                    last_modified.insert(link.clone(), feed_item.meta.date);
+                    if feed_item.meta.noindex {
+                        noindex.insert(link.clone());
+                    }
                     files.insert(link, rendered_content);
                 }
             }
 
+            // Collect search documents of the items which have a page
+            if config.search.is_some() {
+                for feed_item in &feed_items {
+                    let link = match (&feed_item.link, &feed_cfg.index_output) {
+                        (Some(link), _) => format!("/{}", link),
+                        (None, Some(index_output)) => {
+                            format!("/{}#{}", index_output.link.display(), feed_item.file_name)
+                        }
+                        (None, None) => continue,
+                    };
+                    search_documents.push(SearchDocument {
+                        title: feed_item.meta.title.clone(),
+                        subtitle: feed_item.meta.subtitle.clone(),
+                        tags: feed_item.meta.tags.clone().unwrap_or_default(),
+                        link,
+                        text: strip_html(&feed_item.content),
+                    });
+                }
+            }
+
+            // Collect tags
+            let mut tag_items = BTreeMap::<String, Vec<FeedItem>>::new();
+            for feed_item in &feed_items {
+                for tag in feed_item.meta.tags.iter().flatten() {
+                    tag_items
+                        .entry(tag.clone())
+                        .or_default()
+                        .push(feed_item.clone());
+                }
+            }
+            let slugs = match feed_cfg.tag_output {
+                Some(_) => tag_slugs(tag_items.keys(), &feed_cfg.title),
+                None => HashMap::new(),
+            };
+            let tags: Vec<FeedTag> = tag_items
+                .iter()
+                .map(|(tag, items)| FeedTag {
+                    name: tag.clone(),
+                    count: items.len(),
+                    link: feed_cfg
+                        .tag_output
+                        .as_ref()
+                        .zip(slugs.get(tag))
+                        .map(|(tag_output, slug)| tag_output.link.join(slug)),
+                })
+                .collect();
+
             // Generate index
             if let Some(index_output) = &feed_cfg.index_output {
                 let template_path = config.root_dir.join(&index_output.template);
@@ -194,27 +550,38 @@ impl Generator {
                     format!(
                         "Failed to load index template file '{}': {}",
                         &template_path.display(),
-                        err.to_string()
+                        err
                     )
                 })?;
 
                 let index = FeedIndex {
+                    tag: None,
                     feed_link: feed_cfg.rss_feed_link.clone(),
+                    atom_feed_link: feed_cfg.atom_feed_link.clone(),
+                    json_feed_link: feed_cfg.json_feed_link.clone(),
                     items: feed_items.clone(),
+                    tags: tags.clone(),
+                    current_page: 1,
+                    total_pages: 1,
+                    prev_link: None,
+                    next_link: None,
                 };
-                let index_ctx = Context::from_serialize(&index).map_err(|err| err.to_string())?;
-                let index_content = template_engine
-                    .render_string(&index_template, &index_ctx)
-                    .map_err(|err| {
-                        format!(
-                            "Failed to render index template '{}': {}",
-                            &index_output.template.display(),
-                            err.to_string()
-                        )
-                    })?;
-                files.insert(index_output.link.clone(), index_content);
+                render_index_pages(
+                    template_engine,
+                    &index_template,
+                    &index_output.template,
+                    &index_output.link,
+                    index_output.page_size,
+                    index,
+                    &mut files,
+                )?;
             }
 
+            // Only the most recent items are included in the syndication feeds
+            let feed_items_limit = feed_cfg.feed_item_limit.unwrap_or(feed_items.len());
+            let syndicated_items: Vec<FeedItem> =
+                feed_items.iter().take(feed_items_limit).cloned().collect();
+
             // Generate RSS feed
             if let Some(rss_feed_link) = &feed_cfg.rss_feed_link {
                 let index_output = &feed_cfg.index_output.clone().ok_or(format!(
@@ -222,19 +589,285 @@ impl Generator {
                     feed_cfg.title
                 ))?;
                 let rss_str = generate_rss_xml(
-                    &feed_items,
-                    &feed_cfg,
-                    &config.server_name,
-                    &index_output,
-                    &rss_feed_link.to_string_lossy().to_string(),
+                    &syndicated_items,
+                    feed_cfg,
+                    &FeedLinks::new(&config.server_name, index_output, rss_feed_link)?,
                 )?;
                 files.insert(rss_feed_link.clone(), rss_str);
             }
+
+            // Generate Atom feed
+            if let Some(atom_feed_link) = &feed_cfg.atom_feed_link {
+                let index_output = &feed_cfg.index_output.clone().ok_or(format!(
+                    "An index output is required to generate an Atom feed for '{}'!",
+                    feed_cfg.title
+                ))?;
+                let atom_str = generate_atom_xml(
+                    &syndicated_items,
+                    feed_cfg,
+                    &FeedLinks::new(&config.server_name, index_output, atom_feed_link)?,
+                    &source_modified(config, feed_cfg),
+                )?;
+                files.insert(atom_feed_link.clone(), atom_str);
+            }
+
+            // Generate JSON feed
+            if let Some(json_feed_link) = &feed_cfg.json_feed_link {
+                let index_output = &feed_cfg.index_output.clone().ok_or(format!(
+                    "An index output is required to generate a JSON feed for '{}'!",
+                    feed_cfg.title
+                ))?;
+                let json_str = generate_json_feed(
+                    &syndicated_items,
+                    feed_cfg,
+                    &FeedLinks::new(&config.server_name, index_output, json_feed_link)?,
+                )?;
+                files.insert(json_feed_link.clone(), json_str);
+            }
+
+            // Generate tag pages
+            if let Some(tag_output) = &feed_cfg.tag_output {
+                let template_path = config.root_dir.join(&tag_output.template);
+                let tag_template = fs::read_to_string(&template_path).map_err(|err| {
+                    format!(
+                        "Failed to load tag template file '{}': {}",
+                        &template_path.display(),
+                        err
+                    )
+                })?;
+
+                for (tag, tag_feed_items) in &tag_items {
+                    let tag_link = match slugs.get(tag) {
+                        Some(slug) => tag_output.link.join(slug),
+                        None => continue,
+                    };
+                    let tag_feed_link = tag_output
+                        .rss_feed_file
+                        .as_ref()
+                        .map(|file_name| tag_link.join(file_name));
+
+                    let index = FeedIndex {
+                        tag: Some(tag.clone()),
+                        feed_link: tag_feed_link.clone(),
+                        atom_feed_link: None,
+                        json_feed_link: None,
+                        items: tag_feed_items.clone(),
+                        tags: tags.clone(),
+                        current_page: 1,
+                        total_pages: 1,
+                        prev_link: None,
+                        next_link: None,
+                    };
+                    render_index_pages(
+                        template_engine,
+                        &tag_template,
+                        &tag_output.template,
+                        &tag_link,
+                        tag_output.page_size,
+                        index,
+                        &mut files,
+                    )?;
+
+                    // Generate RSS feed for the tag
+                    if let Some(tag_feed_link) = tag_feed_link {
+                        let index_output = &feed_cfg.index_output.clone().ok_or(format!(
+                            "An index output is required to generate tag feeds for '{}'!",
+                            feed_cfg.title
+                        ))?;
+                        let mut tag_feed_cfg = feed_cfg.clone();
+                        tag_feed_cfg.title = format!("{} - {}", feed_cfg.title, tag);
+                        let tag_syndicated_items: Vec<FeedItem> = tag_feed_items
+                            .iter()
+                            .take(feed_items_limit)
+                            .cloned()
+                            .collect();
+                        let rss_str = generate_rss_xml(
+                            &tag_syndicated_items,
+                            &tag_feed_cfg,
+                            &FeedLinks::new(&config.server_name, index_output, &tag_feed_link)?,
+                        )?;
+                        files.insert(tag_feed_link, rss_str);
+                    }
+                }
+            }
+
+            // The other files of a feed change with its newest item
+            if let Some(newest_date) = newest_date {
+                for path in files.keys() {
+                    last_modified.entry(path.clone()).or_insert(newest_date);
+                }
+            }
+            all_files.extend(files);
         }
-        Ok(Self { files })
+        let files = all_files
+            .into_iter()
+            .map(|(path, content)| (path, Arc::new(CachedContent::new(content))))
+            .collect();
+        metrics::generator_rebuild(&config.server_name, start.elapsed());
+
+        Ok(Self {
+            files,
+            last_modified,
+            noindex,
+            search_documents,
+            next_scheduled,
+            feed_counts,
+        })
+    }
+
+    // Adds a file generated from the site after generation, e.g. the sitemap
+    pub fn insert(&mut self, path: PathBuf, content: String) {
+        self.files
+            .insert(path, Arc::new(CachedContent::new(content)));
     }
 
-    pub fn get(&self, path: &PathBuf) -> Option<&String> {
+    pub fn get(&self, path: &PathBuf) -> Option<&Arc<CachedContent>> {
         self.files.get(path)
     }
+
+    // Date of the newest feed item a file was generated from
+    pub fn last_modified(&self, path: &PathBuf) -> Option<NaiveDate> {
+        self.last_modified.get(path).copied()
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files.keys()
+    }
+
+    pub fn is_noindex(&self, path: &PathBuf) -> bool {
+        self.noindex.contains(path)
+    }
+
+    pub fn search_documents(&self) -> &[SearchDocument] {
+        &self.search_documents
+    }
+
+    pub fn next_scheduled(&self) -> Option<NaiveDate> {
+        self.next_scheduled
+    }
+
+    // Keeps the previous version of unchanged files, so Last-Modified only changes with the content
+    pub fn keep_unchanged(&mut self, previous: &Generator) {
+        for (path, content) in self.files.iter_mut() {
+            keep_previous(content, previous.files.get(path));
+        }
+    }
+
+    pub fn feed_counts(&self) -> &[(String, usize)] {
+        &self.feed_counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::{feed_config, post, root_dir, site_config, write_files};
+
+    // Generates a site with the posts in './posts', the index template lists their titles
+    fn generate(name: &str, posts: &[(&str, String)], feed_yaml: &str, drafts: bool) -> Generator {
+        let root_dir = root_dir(name);
+        for (file_name, content) in posts {
+            write_files(&root_dir.join("posts"), &[(file_name, content)]);
+        }
+        write_files(
+            &root_dir,
+            &[(
+                "index.html",
+                "{{ current_page }}/{{ total_pages }} {{ prev_link }} {{ next_link }}:\
+                 {% for item in items %} {{ item.meta.title }}{% endfor %}",
+            )],
+        );
+        let mut config = site_config(&root_dir);
+        config.feeds = vec![feed_config(&format!(
+            "title: Blog
+description: Posts
+source_dir: posts
+{}",
+            feed_yaml
+        ))];
+        let mut template_engine = TemplateEngine::load(&config).unwrap();
+        let generator = Generator::generate(&config, drafts, &mut template_engine).unwrap();
+        fs::remove_dir_all(&root_dir).unwrap();
+        generator
+    }
+
+    fn body(generator: &Generator, path: &str) -> String {
+        generator.get(&PathBuf::from(path)).unwrap().body.clone()
+    }
+
+    #[test]
+    fn slugifies_titles() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  Rust 2018 -- edition "), "rust-2018-edition");
+        assert_eq!(slugify("Ünïcode"), "ünïcode");
+        assert_eq!(slugify("++"), "");
+    }
+
+    #[test]
+    fn tag_slugs_are_unique() {
+        let tags = ["Rust".to_string(), "Web Server".to_string()];
+        let slugs = tag_slugs(tags.iter(), "Blog");
+        assert_eq!(slugs["Web Server"], "web-server");
+
+        let tags = ["C".to_string(), "C++".to_string(), "c-2".to_string()];
+        let slugs = tag_slugs(tags.iter(), "Blog");
+        assert_eq!(slugs["C"], "c");
+        assert_eq!(slugs["C++"], "c-2");
+        assert_eq!(slugs["c-2"], "c-2-2");
+
+        let tags = ["++".to_string(), "Rust".to_string()];
+        let slugs = tag_slugs(tags.iter(), "Blog");
+        assert!(!slugs.contains_key("++"));
+        assert_eq!(slugs["Rust"], "rust");
+    }
+
+    #[test]
+    fn paginates_index() {
+        let posts = [
+            ("a.md", post("A", "2021-01-01", "")),
+            ("b.md", post("B", "2021-01-02", "")),
+            ("c.md", post("C", "2021-01-03", "")),
+        ];
+        let index = "index_output: {template: index.html, link: blog}";
+        let generator = generate("generator-one-page", &posts, index, false);
+        assert_eq!(body(&generator, "blog"), "1/1  : C B A");
+
+        let index = "index_output: {template: index.html, link: blog, page_size: 2}";
+        let generator = generate("generator-pages", &posts, index, false);
+        assert_eq!(body(&generator, "blog"), "1/2  blog/page/2: C B");
+        assert_eq!(body(&generator, "blog/page/2"), "2/2 blog : A");
+        assert!(generator.get(&PathBuf::from("blog/page/1")).is_none());
+        assert!(generator.get(&PathBuf::from("blog/page/3")).is_none());
+    }
+
+    #[test]
+    fn filters_drafts_and_scheduled_posts() {
+        let tomorrow = Local::now().date_naive().succ_opt().unwrap();
+        let next = tomorrow.succ_opt().unwrap();
+        let later = next.succ_opt().unwrap();
+        let posts = [
+            ("published.md", post("Published", "2021-01-01", "")),
+            ("draft.md", post("Draft", "2021-01-02", "draft: true\n")),
+            // Scheduled drafts don't trigger a rebuild
+            (
+                "future-draft.md",
+                post("Future draft", &tomorrow.to_string(), "draft: true\n"),
+            ),
+            ("next.md", post("Next", &next.to_string(), "")),
+            ("later.md", post("Later", &later.to_string(), "")),
+        ];
+        let index = "index_output: {template: index.html, link: blog}";
+        let generator = generate("generator-drafts", &posts, index, false);
+        assert_eq!(body(&generator, "blog"), "1/1  : Published");
+        assert_eq!(generator.next_scheduled(), Some(next));
+        assert_eq!(generator.feed_counts(), &[("Blog".to_string(), 1)]);
+
+        // Preview mode includes everything
+        let generator = generate("generator-preview", &posts, index, true);
+        assert_eq!(
+            body(&generator, "blog"),
+            "1/1  : Later Next Future draft Draft Published"
+        );
+        assert_eq!(generator.next_scheduled(), None);
+    }
 }
@@ -0,0 +1,164 @@
+use crate::{config::HeaderRule, state::SharedState};
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue, CACHE_CONTROL},
+    web, Error,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use globset::{Glob, GlobMatcher};
+use std::task::{Context, Poll};
+
+// Header rules compiled from the config, later rules override earlier ones
+pub struct HeaderRules {
+    rules: Vec<(GlobMatcher, Vec<(HeaderName, HeaderValue)>)>,
+}
+
+impl HeaderRules {
+    pub fn new(rules: &[HeaderRule]) -> Result<Self, String> {
+        let mut compiled = Vec::new();
+        for rule in rules {
+            let matcher = Glob::new(&rule.pattern)
+                .map_err(|err| format!("Invalid header pattern '{}': {}", rule.pattern, err))?
+                .compile_matcher();
+
+            let mut headers = Vec::new();
+            if let Some(cache_control) = &rule.cache_control {
+                headers.push((CACHE_CONTROL, header_value(&rule.pattern, cache_control)?));
+            }
+            for (name, value) in &rule.headers {
+                let name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| {
+                    format!(
+                        "Invalid header name '{}' for '{}': {}",
+                        name, rule.pattern, err
+                    )
+                })?;
+                headers.push((name, header_value(&rule.pattern, value)?));
+            }
+            compiled.push((matcher, headers));
+        }
+        Ok(Self { rules: compiled })
+    }
+
+    // Returns the headers of all rules matching the url path
+    fn matching(&self, path: &str) -> Vec<(HeaderName, HeaderValue)> {
+        self.rules
+            .iter()
+            .filter(|(matcher, _)| matcher.is_match(path))
+            .flat_map(|(_, headers)| headers.iter().cloned())
+            .collect()
+    }
+}
+
+fn header_value(pattern: &str, value: &str) -> Result<HeaderValue, String> {
+    HeaderValue::from_str(value).map_err(|err| {
+        format!(
+            "Invalid header value '{}' for '{}': {}",
+            value, pattern, err
+        )
+    })
+}
+
+// Applies the header rules of the current state to every response
+pub struct ResponseHeaders;
+
+impl<S, B> Transform<S> for ResponseHeaders
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ResponseHeadersMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ResponseHeadersMiddleware { service })
+    }
+}
+
+pub struct ResponseHeadersMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for ResponseHeadersMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let headers = match req.app_data::<web::Data<SharedState>>() {
+            Some(state) => state.get().header_rules.matching(req.path()),
+            None => Vec::new(),
+        };
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            for (name, value) in headers {
+                res.headers_mut().insert(name, value);
+            }
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_globs_in_order() {
+        let rules: Vec<HeaderRule> = serde_yaml::from_str(
+            "- {pattern: '/assets/**', cache_control: 'max-age=31536000'}
+- {pattern: '*.xml', cache_control: 'no-cache', headers: {X-Feed: 'yes'}}
+- {pattern: '/assets/*.css', cache_control: 'max-age=60'}",
+        )
+        .unwrap();
+        let rules = HeaderRules::new(&rules).unwrap();
+        let cache_control = |path: &str| -> Vec<_> {
+            rules
+                .matching(path)
+                .into_iter()
+                .filter(|(name, _)| name == CACHE_CONTROL)
+                .map(|(_, value)| value.to_str().unwrap().to_string())
+                .collect()
+        };
+
+        assert_eq!(cache_control("/assets/img/logo.png"), ["max-age=31536000"]);
+        // Later rules override earlier ones when the headers are inserted
+        assert_eq!(
+            cache_control("/assets/site.css"),
+            ["max-age=31536000", "max-age=60"]
+        );
+        // '*' also matches '/'
+        assert_eq!(cache_control("/blog/feed.xml"), ["no-cache"]);
+        assert!(rules.matching("/blog/feed.xml").contains(&(
+            HeaderName::from_static("x-feed"),
+            HeaderValue::from_static("yes")
+        )));
+        assert!(rules.matching("/index.html").is_empty());
+        assert!(rules.matching("/assets").is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let rules: Vec<HeaderRule> =
+            serde_yaml::from_str("- {pattern: '/a/[', cache_control: 'no-cache'}").unwrap();
+        assert!(HeaderRules::new(&rules).is_err());
+        let rules: Vec<HeaderRule> =
+            serde_yaml::from_str("- {pattern: '/a', headers: {'Bad Name': 'x'}}").unwrap();
+        assert!(HeaderRules::new(&rules).is_err());
+    }
+}
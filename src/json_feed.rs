@@ -0,0 +1,159 @@
+use crate::{
+    atom::date_to_rfc3339,
+    config::FeedConfig,
+    generator::{FeedItem, FeedLinks},
+};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct JsonFeed {
+    version: String,
+    title: String,
+    home_page_url: String,
+    feed_url: String,
+    description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor>,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedAuthor {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    external_url: Option<String>,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    content_html: String,
+    date_published: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonFeedAuthor>,
+}
+
+pub fn generate_json_feed(
+    feed_items: &[FeedItem],
+    feed_cfg: &FeedConfig,
+    links: &FeedLinks,
+) -> Result<String, String> {
+    let mut items = Vec::new();
+    for feed_item in feed_items {
+        let link = links.item(feed_item);
+
+        items.push(JsonFeedItem {
+            id: link.clone(),
+            url: link,
+            external_url: feed_item
+                .meta
+                .links
+                .as_ref()
+                .and_then(|links| links.first())
+                .map(|link| link.url.clone()),
+            title: feed_item.meta.title.clone(),
+            summary: feed_item.meta.subtitle.clone(),
+            content_html: feed_item.content.clone(),
+            date_published: date_to_rfc3339(&feed_item.meta.date),
+            image: feed_item
+                .meta
+                .image
+                .as_ref()
+                .map(|image| links.absolute(&image.file_name)),
+            tags: feed_item.meta.tags.clone().unwrap_or_default(),
+            authors: feed_item
+                .meta
+                .author
+                .clone()
+                .map(|name| vec![JsonFeedAuthor { name }])
+                .unwrap_or_default(),
+        });
+    }
+
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1".to_string(),
+        title: feed_cfg.title.clone(),
+        home_page_url: links.index.clone(),
+        feed_url: links.feed.clone(),
+        description: feed_cfg.description.clone(),
+        authors: feed_cfg
+            .author
+            .clone()
+            .map(|name| vec![JsonFeedAuthor { name }])
+            .unwrap_or_default(),
+        items,
+    };
+
+    serde_json::to_string_pretty(&feed).map_err(|err| format!("JSON Serialization error: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_site::{feed_config, feed_item};
+    use serde_json::{json, Value};
+    use std::path::Path;
+
+    #[test]
+    fn generates_json_feed() {
+        let feed_cfg =
+            feed_config("{title: Blog, description: Posts, source_dir: posts, author: Jane}");
+        let index_output = serde_yaml::from_str("{template: index.html, link: blog}").unwrap();
+        let links =
+            FeedLinks::new("https://example.com", &index_output, Path::new("feed.json")).unwrap();
+        let mut first = feed_item(
+            "first",
+            "{title: First, subtitle: Intro, date: 2021-03-04, content_type: md, tags: [rust],
+              image: {alt: Logo, file_name: /img/first.png}, links: [{url: 'https://example.org', content: Source}]}",
+            "<p>A & B</p>",
+        );
+        first.link = Some("blog/first".to_string());
+        let second = feed_item(
+            "second",
+            "{title: Second, date: 2021-03-01, content_type: md}",
+            "",
+        );
+
+        let feed = generate_json_feed(&[first, second], &feed_cfg, &links).unwrap();
+        let feed: Value = serde_json::from_str(&feed).unwrap();
+        assert_eq!(
+            feed,
+            json!({
+                "version": "https://jsonfeed.org/version/1.1",
+                "title": "Blog",
+                "home_page_url": "https://example.com/blog",
+                "feed_url": "https://example.com/feed.json",
+                "description": "Posts",
+                "authors": [{"name": "Jane"}],
+                "items": [
+                    {
+                        "id": "https://example.com/blog/first",
+                        "url": "https://example.com/blog/first",
+                        "external_url": "https://example.org",
+                        "title": "First",
+                        "summary": "Intro",
+                        "content_html": "<p>A & B</p>",
+                        "date_published": "2021-03-04T00:00:00Z",
+                        "image": "https://example.com/img/first.png",
+                        "tags": ["rust"]
+                    },
+                    {
+                        "id": "https://example.com/blog#second",
+                        "url": "https://example.com/blog#second",
+                        "title": "Second",
+                        "content_html": "",
+                        "date_published": "2021-03-01T00:00:00Z"
+                    }
+                ]
+            })
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    config::LinkCheckConfig,
+    file_server::{generated_mime, resolve, routes, Route},
+    state::Site,
+};
+use percent_encoding::percent_decode_str;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+};
+
+// Maximum number of redirects followed when checking a link
+const MAX_REDIRECTS: usize = 10;
+
+// Links and anchors of a rendered page
+struct Page {
+    links: Vec<String>,
+    anchors: HashSet<String>,
+}
+
+// Crawls the rendered pages of the sites, writes the report and fails in strict mode
+pub fn run(config: &LinkCheckConfig, sites: &[&Site]) -> Result<(), String> {
+    let mut report = String::new();
+    let mut count = 0;
+    for site in sites {
+        let problems = check_links(site)?;
+        if !problems.is_empty() {
+            report.push_str(&format!(
+                "Found {} broken link(s) in '{}':\n",
+                problems.len(),
+                site.config.server_name
+            ));
+            for problem in &problems {
+                report.push_str(&format!("- {}\n", problem));
+            }
+            count += problems.len();
+        }
+    }
+
+    match &config.report_file {
+        Some(report_file) => fs::write(report_file, &report).map_err(|err| {
+            format!(
+                "Failed to write link report '{}': {}",
+                report_file.display(),
+                err
+            )
+        })?,
+        None => eprint!("{}", report),
+    }
+
+    if config.strict && count > 0 {
+        Err(format!("Found {} broken link(s)!", count))
+    } else {
+        Ok(())
+    }
+}
+
+// Returns the dead internal links and missing anchors of all rendered pages
+pub fn check_links(site: &Site) -> Result<Vec<String>, String> {
+    let mut pages = BTreeMap::new();
+    for route in routes(site)? {
+        if let Some(html) = page_html(site, &route) {
+            let page = Page {
+                links: attribute_values(&html, &["href=", "src="]),
+                anchors: anchors(&html),
+            };
+            pages.insert(route, page);
+        }
+    }
+
+    let mut problems = Vec::new();
+    for (route, page) in &pages {
+        for link in &page.links {
+            let target = if link.starts_with('#') {
+                route.clone()
+            } else {
+                match internal_path(route, link) {
+                    Some(path) => match served_path(site, &path) {
+                        Some(target) => target,
+                        None => {
+                            problems.push(format!("'/{}': Broken link '{}'", route, link));
+                            continue;
+                        }
+                    },
+                    None => continue,
+                }
+            };
+
+            // Anchors can only be checked on pages, 'top' is implicit
+            let fragment = decode(link.split_once('#').map_or("", |(_, fragment)| fragment));
+            if fragment.is_empty() || fragment == "top" {
+                continue;
+            }
+            if let Some(target_page) = pages.get(&target) {
+                if !target_page.anchors.contains(&fragment) {
+                    problems.push(format!("'/{}': Missing anchor in '{}'", route, link));
+                }
+            }
+        }
+    }
+    Ok(problems)
+}
+
+// Whether a link relative to the root is served, external links are assumed to exist
+pub fn link_exists(site: &Site, link: &str) -> bool {
+    match internal_path("", link) {
+        Some(path) => served_path(site, &path).is_some(),
+        None => true,
+    }
+}
+
+// Html of a page, pages which fail to render are skipped
+fn page_html(site: &Site, route: &str) -> Option<String> {
+    match resolve(site, route) {
+        Route::Rendered(relative_path) => site
+            .render_page(&relative_path)
+            .ok()
+            .map(|content| content.body.clone()),
+        Route::Generated(path) if generated_mime(&path) == mime::TEXT_HTML => site
+            .generator
+            .get(&path)
+            .map(|content| content.body.clone()),
+        _ => None,
+    }
+}
+
+// Targets of fragments, the ids of all elements and the names of <a> elements
+fn anchors(html: &str) -> HashSet<String> {
+    let mut anchors: HashSet<String> = attribute_positions(html, "id=")
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect();
+    for (position, value) in attribute_positions(html, "name=") {
+        // The name of e.g. <meta> or <input> isn't an anchor
+        let tag = match html[..position].rfind('<') {
+            Some(start) => &html[start + 1..position],
+            None => continue,
+        };
+        let tag_name = tag.split_whitespace().next().unwrap_or("");
+        if tag_name.eq_ignore_ascii_case("a") {
+            anchors.insert(value);
+        }
+    }
+    anchors
+}
+
+// Values of the given attributes, e.g. 'href='
+fn attribute_values(html: &str, attributes: &[&str]) -> Vec<String> {
+    attributes
+        .iter()
+        .flat_map(|attribute| attribute_positions(html, attribute))
+        .map(|(_, value)| value)
+        .collect()
+}
+
+// Values of an attribute with the position of the attribute in the html
+fn attribute_positions(html: &str, attribute: &str) -> Vec<(usize, String)> {
+    let mut values = Vec::new();
+    let mut rest = html;
+    while let Some(i) = rest.find(attribute) {
+        rest = &rest[i + attribute.len()..];
+        // Skip attributes which are part of a longer name, e.g. data-src
+        let preceding = html.len() - rest.len() - attribute.len();
+        if preceding > 0 && !html.as_bytes()[preceding - 1].is_ascii_whitespace() {
+            continue;
+        }
+        let quote = match rest.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => continue,
+        };
+        if let Some(end) = rest[1..].find(quote) {
+            values.push((preceding, rest[1..end + 1].trim().to_string()));
+            rest = &rest[end + 1..];
+        }
+    }
+    values
+}
+
+// Links are percent-encoded, routes and anchors aren't
+fn decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().into_owned()
+}
+
+// Resolves a link on a page to a path without leading slash, external links return None
+fn internal_path(route: &str, link: &str) -> Option<String> {
+    if link.is_empty()
+        || link.starts_with('#')
+        || link.starts_with("//")
+        || (link.contains(':') && !link.starts_with('/'))
+    {
+        return None;
+    }
+    // Query and fragment don't change the route
+    let link = decode(link.split(['?', '#']).next().unwrap_or(""));
+
+    let joined = if let Some(absolute) = link.strip_prefix('/') {
+        absolute.to_string()
+    } else {
+        // Relative links are resolved against the directory of the page
+        match route.rfind('/') {
+            Some(i) => format!("{}/{}", &route[..i], link),
+            None => link.to_string(),
+        }
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in joined.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+// Path which is finally served for a path, following redirects
+fn served_path(site: &Site, path: &str) -> Option<String> {
+    let mut path = path.to_string();
+    for _ in 0..MAX_REDIRECTS {
+        match resolve(site, &path) {
+            Route::NotFound => return None,
+            Route::Redirect(target) => match internal_path("", &target) {
+                Some(target) => path = target,
+                // Redirects to external urls are assumed to exist
+                None => return Some(path),
+            },
+            Route::StaticFile(_) | Route::Generated(_) | Route::Rendered(_) | Route::Search => {
+                return Some(path)
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_internal_paths() {
+        assert_eq!(
+            internal_path("posts/a.html", "b.html"),
+            Some("posts/b.html".to_string())
+        );
+        assert_eq!(
+            internal_path("posts/a.html", "../index.html#top"),
+            Some("index.html".to_string())
+        );
+        assert_eq!(internal_path("", "/a%20b?x=1"), Some("a b".to_string()));
+        assert_eq!(internal_path("", "https://example.com/"), None);
+        assert_eq!(internal_path("", "//example.com/"), None);
+        assert_eq!(internal_path("", "#section"), None);
+    }
+
+    #[test]
+    fn collects_anchors() {
+        let html = r#"<meta name="robots"><h2 id="intro">Intro</h2><a name="old">x</a><input name="q"><div data-id="no">"#;
+        let anchors = anchors(html);
+        let mut anchors: Vec<&str> = anchors.iter().map(String::as_str).collect();
+        anchors.sort_unstable();
+        assert_eq!(anchors, vec!["intro", "old"]);
+    }
+}
@@ -1,68 +1,317 @@
+mod access_log;
+mod acme;
+mod atom;
+mod check;
+mod cli;
 mod config;
+mod content_cache;
 mod error_handler;
+mod export;
 mod file_server;
+mod front_matter;
 mod generator;
+mod headers;
+mod json_feed;
+mod link_checker;
+mod metrics;
+mod new_post;
+mod page;
+mod reload;
+mod robots;
 mod rss;
+mod search;
+mod sitemap;
+mod socket;
+mod state;
 mod template_engine;
+#[cfg(test)]
+mod test_site;
+mod tls;
 
 use crate::{
-    config::ServerConfig, error_handler::handle_errors, generator::Generator,
-    template_engine::TemplateEngine,
+    access_log::{AccessLog, LogWriter},
+    acme::spawn_acme,
+    check::check,
+    cli::{Cli, Command},
+    config::{ConfigOverrides, ServerConfig, SiteConfig},
+    error_handler::handle_errors,
+    export::{export, out_dirs},
+    headers::{HeaderRules, ResponseHeaders},
+    metrics::{metrics, RequestMetrics},
+    new_post::new_post,
+    reload::{spawn_scheduler, spawn_watcher},
+    socket::{notify_ready, spawn_restart_handler, Listeners},
+    state::{matches_host, ServerState, SharedState},
+    tls::{acme_challenge_dir, hsts_header, load_rustls_config, HttpsRedirect},
 };
 use actix_web::{web, App, HttpServer};
+use clap::Parser;
 use file_server::files;
+use futures::future::try_join;
+use std::path::{Path, PathBuf};
 
 const CONFIG_SUBDIR: &str = "webserver";
 const SERVER_CONFIG_FILE: &str = "config.yaml";
+// Seconds to wait for in-flight requests when stopping
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
 
 #[actix_web::main]
 async fn main() -> Result<(), std::io::Error> {
-    let config_dir = match dirs::config_dir() {
-        Some(dir) => dir.join(CONFIG_SUBDIR),
-        None => {
-            eprintln!("Failed to get config dir!");
-            std::process::exit(1);
-        }
+    let cli = Cli::parse();
+
+    let config_path = match cli.config {
+        Some(path) => path,
+        None => match dirs::config_dir() {
+            Some(dir) => dir.join(CONFIG_SUBDIR).join(SERVER_CONFIG_FILE),
+            None => {
+                eprintln!("Failed to get config dir!");
+                std::process::exit(1);
+            }
+        },
+    };
+    let overrides = ConfigOverrides {
+        root_dir: cli.root,
+        address: cli.address,
+        port: cli.port,
+        drafts: cli.drafts,
     };
 
-    let config = match ServerConfig::load(config_dir.join(SERVER_CONFIG_FILE)) {
-        Ok(config) => config,
-        Err(err) => {
-            eprintln!("Failed to load configuration file!");
-            eprintln!("Error: {}", err);
-            std::process::exit(1);
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config_path, overrides).await,
+        Command::Build { out } => {
+            let state = load_state(&config_path, &overrides);
+            check_links(&state);
+            let site_configs: Vec<&SiteConfig> =
+                state.all_sites().map(|site| &site.config).collect();
+            let site_outs = match out_dirs(&site_configs, &out) {
+                Ok(site_outs) => site_outs,
+                Err(err) => {
+                    eprintln!("Failed to export sites!");
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+            for (site, site_out) in state.all_sites().zip(site_outs) {
+                match export(site, &site_out) {
+                    Ok(count) => {
+                        println!("Exported {} routes to '{}'", count, site_out.display())
+                    }
+                    Err(err) => {
+                        eprintln!("Failed to export site '{}'!", site.config.server_name);
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            Ok(())
         }
-    };
+        Command::Check => {
+            let config = match ServerConfig::load(config_path, &overrides) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("Failed to load configuration file!");
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+            let mut failed = false;
+            if let Err(err) = HeaderRules::new(&config.headers) {
+                eprintln!("Invalid headers config: {}", err);
+                failed = true;
+            }
+            if let Some(Err(err)) = config.tls.as_ref().map(hsts_header) {
+                eprintln!("Invalid TLS config: {}", err);
+                failed = true;
+            }
+            for site_config in std::iter::once(&config.site).chain(config.sites.iter()) {
+                match check(site_config, config.drafts) {
+                    Ok(count) => println!(
+                        "Checked {} routes of '{}', no problems found",
+                        count, site_config.server_name
+                    ),
+                    Err(problems) => {
+                        eprintln!(
+                            "Found {} problem(s) in '{}':",
+                            problems.len(),
+                            site_config.server_name
+                        );
+                        for problem in problems {
+                            eprintln!("- {}", problem);
+                        }
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::NewPost { title, feed, site } => {
+            let config = match ServerConfig::load(config_path, &overrides) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("Failed to load configuration file!");
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+            let site_config = match &site {
+                Some(host) => match config
+                    .sites
+                    .iter()
+                    .find(|site_config| matches_host(&site_config.hosts, host))
+                {
+                    Some(site_config) => site_config,
+                    None => {
+                        eprintln!("No site found with host '{}'!", host);
+                        std::process::exit(1);
+                    }
+                },
+                None => &config.site,
+            };
+            match new_post(site_config, &title, feed.as_deref()) {
+                Ok(path) => {
+                    println!("Created '{}'", path.display());
+                    Ok(())
+                }
+                Err(err) => {
+                    eprintln!("Failed to create post!");
+                    eprintln!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
 
-    let mut template_engine = match TemplateEngine::load(&config) {
-        Ok(template_engine) => template_engine,
+fn load_state(config_path: &Path, overrides: &ConfigOverrides) -> ServerState {
+    match ServerState::load(config_path, overrides) {
+        Ok(state) => state,
         Err(err) => {
-            eprintln!("Failed to load template engine!");
+            eprintln!("Failed to load server!");
             eprintln!("Error: {}", err);
             std::process::exit(1);
         }
-    };
+    }
+}
+
+// Exits in strict mode when there are broken links
+fn check_links(state: &ServerState) {
+    if let Err(err) = state.check_links() {
+        eprintln!("Link check failed!");
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn serve(config_path: PathBuf, overrides: ConfigOverrides) -> Result<(), std::io::Error> {
+    let state = load_state(&config_path, &overrides);
+    // Rendering every page is too slow to repeat on each reload
+    if state
+        .config
+        .link_check
+        .as_ref()
+        .is_some_and(|link_check| link_check.serve)
+    {
+        check_links(&state);
+    }
 
-    let generator = match Generator::generate(&config, &mut template_engine) {
-        Ok(generator) => generator,
+    // Changing the address, port, TLS, access log or metrics config requires a restart
+    let config = state.config.clone();
+    // Takes the inherited sockets out of the environment, before any thread is started
+    let listeners = Listeners::open(&config)?;
+    let tls = config.tls.clone();
+    let root_dir = config.site.root_dir.clone();
+    let hsts = match tls.as_ref().map(hsts_header).transpose() {
+        Ok(hsts) => hsts.flatten(),
         Err(err) => {
-            eprintln!("Failed to generate feeds!");
+            eprintln!("Failed to load TLS config!");
             eprintln!("Error: {}", err);
             std::process::exit(1);
         }
     };
+    let rustls_config = match &tls {
+        Some(tls) => match load_rustls_config(tls) {
+            Ok((rustls_config, resolver)) => {
+                if let Some(acme) = &tls.acme {
+                    let challenge_dir = acme_challenge_dir(tls, &root_dir);
+                    if let Err(err) = spawn_acme(acme.clone(), challenge_dir, resolver) {
+                        eprintln!("Failed to start ACME certificate provisioning!");
+                        eprintln!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+                Some(rustls_config)
+            }
+            Err(err) => {
+                eprintln!("Failed to load TLS config!");
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let access_log = match &state.config.access_log {
+        Some(access_log) => match LogWriter::spawn(access_log) {
+            Ok(sender) => Some(sender),
+            Err(err) => {
+                eprintln!("Failed to open access log!");
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let state = web::Data::new(SharedState::new(config_path, overrides, state));
 
-    let socket = (config.address, config.port);
+    if let Err(err) = spawn_watcher(state.clone()) {
+        eprintln!("Failed to start file watcher, changes will require a restart!");
+        eprintln!("Error: {}", err);
+    }
+    spawn_scheduler(state.clone());
 
-    HttpServer::new(move || {
+    let shutdown_timeout = config.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+    let metrics_enabled = config.metrics.is_some();
+    let server = HttpServer::new(move || {
         App::new()
-            .data(config.clone())
-            .data(template_engine.clone())
-            .data(generator.clone())
+            .app_data(state.clone())
+            .wrap(ResponseHeaders)
+            .wrap(HttpsRedirect::new(tls.as_ref(), &root_dir, hsts.clone()))
+            .wrap(AccessLog::new(access_log.clone()))
+            .wrap(RequestMetrics::new(metrics_enabled))
             .default_service(web::get().to(files))
             .service(web::scope("").wrap(handle_errors()))
     })
-    .bind(socket)?
-    .run()
-    .await
+    .shutdown_timeout(shutdown_timeout)
+    .listen(listeners.http.try_clone()?)?;
+
+    let server = match (rustls_config, &listeners.https) {
+        (Some(rustls_config), Some(https)) => {
+            server.listen_rustls(https.try_clone()?, rustls_config)?
+        }
+        _ => server,
+    };
+    let server = server.run();
+
+    // The metrics endpoint is served on its own address, so it isn't public
+    match &listeners.metrics {
+        Some(metrics_listener) => {
+            let metrics_server =
+                HttpServer::new(|| App::new().route("/metrics", web::get().to(metrics)))
+                    .workers(1)
+                    .shutdown_timeout(shutdown_timeout)
+                    .listen(metrics_listener.try_clone()?)?
+                    .run();
+            spawn_restart_handler(&listeners, vec![server.clone(), metrics_server.clone()]);
+            notify_ready();
+            try_join(server, metrics_server).await?;
+            Ok(())
+        }
+        None => {
+            spawn_restart_handler(&listeners, vec![server.clone()]);
+            notify_ready();
+            server.await
+        }
+    }
 }
@@ -0,0 +1,188 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpResponse,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::{
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+lazy_static! {
+    static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "http_requests_total",
+        "Number of HTTP requests by status and route kind",
+        &["status", "route"]
+    )
+    .expect("Failed to register metric!");
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "http_request_duration_seconds",
+        "HTTP request latency by status and route kind",
+        &["status", "route"]
+    )
+    .expect("Failed to register metric!");
+    static ref TEMPLATE_RENDER_ERRORS: IntCounter = register_int_counter!(
+        "template_render_errors_total",
+        "Number of failed template renders"
+    )
+    .expect("Failed to register metric!");
+    static ref GENERATOR_DURATION: HistogramVec = register_histogram_vec!(
+        "generator_duration_seconds",
+        "Duration of a generator rebuild by site",
+        &["site"]
+    )
+    .expect("Failed to register metric!");
+    static ref FEED_ITEMS: IntGaugeVec = register_int_gauge_vec!(
+        "feed_items",
+        "Number of published items by site and feed",
+        &["site", "feed"]
+    )
+    .expect("Failed to register metric!");
+}
+
+// Kind of route which handled a request, stored in the request extensions
+// because error pages replace the response
+#[derive(Clone, Copy)]
+pub enum RouteKind {
+    StaticFile,
+    Rendered,
+    Generated,
+    Redirect,
+    Search,
+    NotFound,
+}
+
+impl RouteKind {
+    fn label(&self) -> &'static str {
+        match self {
+            RouteKind::StaticFile => "static",
+            RouteKind::Rendered => "rendered",
+            RouteKind::Generated => "generated",
+            RouteKind::Redirect => "redirect",
+            RouteKind::Search => "search",
+            RouteKind::NotFound => "not_found",
+        }
+    }
+}
+
+pub fn template_render_error() {
+    TEMPLATE_RENDER_ERRORS.inc();
+}
+
+pub fn generator_rebuild(site: &str, duration: Duration) {
+    GENERATOR_DURATION
+        .with_label_values(&[site])
+        .observe(duration.as_secs_f64());
+}
+
+// Feeds removed on reload would keep their last value otherwise
+pub fn reset_feed_items() {
+    FEED_ITEMS.reset();
+}
+
+pub fn feed_items(site: &str, feed: &str, count: usize) {
+    FEED_ITEMS
+        .with_label_values(&[site, feed])
+        .set(count as i64);
+}
+
+// Handler of the metrics endpoint, in the Prometheus text format
+pub async fn metrics() -> HttpResponse {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    match encoder.encode(&prometheus::gather(), &mut buffer) {
+        Ok(()) => HttpResponse::Ok()
+            .content_type(encoder.format_type())
+            .body(buffer),
+        Err(err) => {
+            eprintln!("Failed to encode metrics: {}", err);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+// Records the status, route kind and latency of every request
+pub struct RequestMetrics {
+    enabled: bool,
+}
+
+impl RequestMetrics {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl<S, B> Transform<S> for RequestMetrics
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestMetricsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestMetricsMiddleware {
+            service,
+            enabled: self.enabled,
+        })
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: S,
+    enabled: bool,
+}
+
+impl<S, B> Service for RequestMetricsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if !self.enabled {
+            return Box::pin(self.service.call(req));
+        }
+        let start = Instant::now();
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let result = fut.await;
+            let (status, route) = match &result {
+                Ok(res) => (
+                    res.status(),
+                    res.request()
+                        .extensions()
+                        .get::<RouteKind>()
+                        .map(|kind| kind.label())
+                        .unwrap_or("other"),
+                ),
+                Err(err) => (err.as_response_error().status_code(), "other"),
+            };
+            let labels = [status.as_str(), route];
+            HTTP_REQUESTS.with_label_values(&labels).inc();
+            HTTP_REQUEST_DURATION
+                .with_label_values(&labels)
+                .observe(start.elapsed().as_secs_f64());
+            result
+        })
+    }
+}
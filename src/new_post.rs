@@ -0,0 +1,56 @@
+use crate::{
+    config::{FeedConfig, SiteConfig},
+    generator::slugify,
+};
+use chrono::Local;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Creates a new markdown post in the source dir of a feed and returns its path
+pub fn new_post(config: &SiteConfig, title: &str, feed: Option<&str>) -> Result<PathBuf, String> {
+    let feed_cfg = find_feed(config, feed)?;
+
+    let slug = slugify(title);
+    if slug.is_empty() {
+        return Err(format!("Cannot create a file name from title '{}'!", title));
+    }
+
+    let source_dir = config.root_dir.join(&feed_cfg.source_dir);
+    fs::create_dir_all(&source_dir)
+        .map_err(|err| format!("Failed to create dir '{}': {}", source_dir.display(), err))?;
+    let path = source_dir.join(format!("{}.md", slug));
+    if path.exists() {
+        return Err(format!("File '{}' already exists!", path.display()));
+    }
+
+    let content = format!(
+        "---\ntitle: {}\ndate: {}\ncontent_type: md\n---\n",
+        serde_yaml::to_string(title)
+            .map_err(|err| err.to_string())?
+            .trim_start_matches("---")
+            .trim(),
+        Local::now().date_naive().format("%Y-%m-%d")
+    );
+    fs::write(&path, content)
+        .map_err(|err| format!("Failed to write file '{}': {}", path.display(), err))?;
+    Ok(path)
+}
+
+fn find_feed<'a>(config: &'a SiteConfig, feed: Option<&str>) -> Result<&'a FeedConfig, String> {
+    match feed {
+        Some(name) => config
+            .feeds
+            .iter()
+            .find(|feed_cfg| feed_cfg.title == name || feed_cfg.source_dir == Path::new(name))
+            .ok_or(format!(
+                "No feed found with title or source dir '{}'!",
+                name
+            )),
+        None => config
+            .feeds
+            .first()
+            .ok_or("There are no feeds in the config!".to_string()),
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    config::{MarkdownConfig, SiteConfig},
+    front_matter,
+    generator::comrak_options,
+    state::Site,
+};
+use comrak::markdown_to_html;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tera::Context;
+
+pub const MARKDOWN_EXT: &str = "md";
+
+#[derive(Deserialize, Default)]
+struct PageMeta {
+    // Template the page is rendered into, relative to the root dir and in the layout dir
+    layout: Option<PathBuf>,
+    // Overrides the markdown options of the site
+    #[serde(default)]
+    markdown: MarkdownConfig,
+    // Excluded from the sitemap and search, served with 'X-Robots-Tag: noindex'
+    #[serde(default)]
+    noindex: bool,
+}
+
+// Whether a file relative to the root dir is a markdown page,
+// markdown files in feed source dirs are feed items
+pub fn is_markdown_page(config: &SiteConfig, relative_path: &Path) -> bool {
+    relative_path
+        .extension()
+        .is_some_and(|ext| ext == MARKDOWN_EXT)
+        && config.root_dir.join(relative_path).is_file()
+        && !config
+            .feeds
+            .iter()
+            .any(|feed_cfg| is_in_dir(config, relative_path, &feed_cfg.source_dir))
+}
+
+// Whether a file is directly in a dir, both relative to the root dir,
+// joining them normalizes e.g. './posts' and 'posts/'
+fn is_in_dir(config: &SiteConfig, relative_path: &Path, dir: &Path) -> bool {
+    relative_path
+        .parent()
+        .map(|parent| config.root_dir.join(parent))
+        == Some(config.root_dir.join(dir))
+}
+
+// Whether a file relative to the root dir is in the layout dir or one of its subdirs
+pub fn is_layout(config: &SiteConfig, relative_path: &Path) -> bool {
+    config.layout_dir.as_ref().is_some_and(|dir| {
+        config
+            .root_dir
+            .join(relative_path)
+            .starts_with(config.root_dir.join(dir))
+    })
+}
+
+// Whether a markdown page sets 'noindex' in its front matter, without rendering it
+pub fn is_noindex_page(config: &SiteConfig, relative_path: &Path) -> bool {
+    relative_path
+        .extension()
+        .is_some_and(|ext| ext == MARKDOWN_EXT)
+        && read_page(&config.root_dir.join(relative_path))
+            .is_ok_and(|(_, page_meta, _)| page_meta.noindex)
+}
+
+// Renders a markdown page into its layout, the front matter and the html
+// ('content') are available in the layout. Returns the page and whether it is noindex
+pub fn render_markdown_page(
+    site: &Site,
+    relative_path: &PathBuf,
+) -> Result<(String, bool), String> {
+    let (meta, page_meta, body) = read_page(&site.config.root_dir.join(relative_path))?;
+    let markdown = site.config.markdown.merge_content(&page_meta.markdown)?;
+    let html = markdown_to_html(&body, &comrak_options(&markdown));
+    // A page without layout would be served as a bare html fragment
+    let layout = match page_meta.layout {
+        // Otherwise the layout would be served and checked as a page itself
+        Some(layout) if !is_layout(&site.config, &layout) => {
+            return Err(format!(
+                "Layout '{}' of '{}' must be in the 'layout_dir' of the site",
+                layout.display(),
+                relative_path.display()
+            ))
+        }
+        Some(layout) => layout,
+        None => site.config.page_layout.clone().ok_or_else(|| {
+            "No layout, set 'layout' in the front matter or 'page_layout' of the site".to_string()
+        })?,
+    };
+    let mut context = if meta.is_object() {
+        Context::from_value(meta).map_err(|err| err.to_string())?
+    } else {
+        Context::new()
+    };
+    context.insert("content", &html);
+    let page = site.template_engine.render_file(layout, &context)?;
+    Ok((page, page_meta.noindex))
+}
+
+// Returns the front matter, the page metadata and the markdown of a page
+fn read_page(path: &Path) -> Result<(Value, PageMeta, String), String> {
+    let file_str = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read '{}': {}", path.display(), err))?;
+    let (meta, body) = match front_matter::split(&file_str) {
+        Some(front_matter) => (front_matter.parse::<Value>(path)?, front_matter.body),
+        None => (Value::Null, file_str.as_str()),
+    };
+    // Empty front matter has no fields
+    let page_meta = if meta.is_object() {
+        serde_json::from_value::<PageMeta>(meta.clone())
+            .map_err(|err| format!("{}: {}", path.display(), err))?
+    } else {
+        PageMeta::default()
+    };
+    Ok((meta, page_meta, body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        file_server::is_template,
+        test_site::{feed_config, root_dir, write_files},
+    };
+
+    // Site in a temp dir with a feed in './posts'
+    fn site_config(name: &str) -> SiteConfig {
+        let root_dir = root_dir(name);
+        write_files(
+            &root_dir,
+            &[
+                ("about.md", "---\nnoindex: true\n---\n# About\n"),
+                ("posts/post.md", "# Post\n"),
+            ],
+        );
+        let mut config = crate::test_site::site_config(&root_dir);
+        config.feeds = vec![feed_config(
+            "{title: Posts, description: Posts, source_dir: ./posts}",
+        )];
+        config
+    }
+
+    #[test]
+    fn feed_items_are_not_pages() {
+        let config = site_config("page-feed-items");
+        assert!(is_markdown_page(&config, Path::new("about.md")));
+        assert!(!is_markdown_page(&config, Path::new("posts/post.md")));
+        assert!(!is_markdown_page(&config, Path::new("missing.md")));
+        fs::remove_dir_all(&config.root_dir).unwrap();
+    }
+
+    #[test]
+    fn noindex_from_front_matter() {
+        let config = site_config("page-noindex");
+        assert!(is_noindex_page(&config, Path::new("about.md")));
+        assert!(!is_noindex_page(&config, Path::new("posts/post.md")));
+        fs::remove_dir_all(&config.root_dir).unwrap();
+    }
+
+    #[test]
+    fn layouts_must_be_in_layout_dir() {
+        let root_dir = root_dir("page-layouts");
+        write_files(
+            &root_dir,
+            &[
+                ("layouts/page.html", "<main>{{ content }}</main>"),
+                ("other.html", "{{ content }}"),
+                ("inside.md", "---\nlayout: layouts/page.html\n---\nText\n"),
+                ("outside.md", "---\nlayout: other.html\n---\nText\n"),
+            ],
+        );
+        let mut config = crate::test_site::site_config(&root_dir);
+        config.layout_dir = Some(PathBuf::from("./layouts"));
+        assert!(is_template(&config, &PathBuf::from("layouts/page.html")));
+        assert!(!is_template(&config, &PathBuf::from("other.html")));
+
+        let site = Site::load(config, false).unwrap();
+        let (html, _) = render_markdown_page(&site, &PathBuf::from("inside.md")).unwrap();
+        assert_eq!(html, "<main><p>Text</p>\n</main>");
+        assert!(render_markdown_page(&site, &PathBuf::from("outside.md")).is_err());
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+}
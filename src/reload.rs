@@ -0,0 +1,145 @@
+use crate::state::{ServerState, SharedState};
+use actix_web::web::Data;
+use chrono::{Local, NaiveDate};
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{env, path::PathBuf, sync::mpsc::channel, thread, time::Duration};
+
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+// Watches the root directories of all sites and the config file and reloads the state on changes
+pub fn spawn_watcher(state: Data<SharedState>) -> Result<(), String> {
+    let (tx, rx) = channel();
+    let mut watcher = watcher(tx, DEBOUNCE_DELAY)
+        .map_err(|err| format!("Failed to create file watcher: {}", err))?;
+
+    // Watch the directory of the config file, editors often replace the file instead of writing to it
+    let config_path = absolute(state.config_path());
+    let config_dir = match config_path.parent() {
+        Some(dir) => dir.to_path_buf(),
+        None => config_path.clone(),
+    };
+    watcher
+        .watch(&config_dir, RecursiveMode::NonRecursive)
+        .map_err(|err| {
+            format!(
+                "Failed to watch config dir '{}': {}",
+                config_dir.display(),
+                err
+            )
+        })?;
+
+    let mut watched_dirs = root_dirs(&state.get());
+    for root_dir in &watched_dirs {
+        watch_root_dir(&mut watcher, root_dir)?;
+    }
+
+    thread::spawn(move || {
+        // The watcher must live as long as the thread
+        let mut watcher = watcher;
+        for event in rx {
+            let paths = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path) => vec![path],
+                // Moving a file out of a watched dir changes it as well
+                DebouncedEvent::Rename(from, to) => vec![from, to],
+                DebouncedEvent::Rescan => vec![config_path.clone()],
+                DebouncedEvent::Error(err, _) => {
+                    eprintln!("File watcher error: {}", err);
+                    continue;
+                }
+                _ => continue,
+            };
+            // Ignore changes to other files in the config dir
+            let path = match paths.into_iter().find(|path| {
+                *path == config_path
+                    || watched_dirs
+                        .iter()
+                        .any(|root_dir| path.starts_with(root_dir))
+            }) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            match state.reload() {
+                Ok(()) => {
+                    println!("Reloaded after change in '{}'", path.display());
+                    // Sites may have been changed in the config
+                    let new_root_dirs = root_dirs(&state.get());
+                    for root_dir in &watched_dirs {
+                        if !new_root_dirs.contains(root_dir) {
+                            let _ = watcher.unwatch(root_dir);
+                        }
+                    }
+                    for root_dir in &new_root_dirs {
+                        if !watched_dirs.contains(root_dir) {
+                            if let Err(err) = watch_root_dir(&mut watcher, root_dir) {
+                                eprintln!("{}", err);
+                            }
+                        }
+                    }
+                    watched_dirs = new_root_dirs;
+                }
+                Err(err) => {
+                    eprintln!("Failed to reload, keeping the previous version!");
+                    eprintln!("Error: {}", err);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+// Regenerates when a scheduled post should be published
+pub fn spawn_scheduler(state: Data<SharedState>) {
+    thread::spawn(move || {
+        // Don't retry every interval if a regeneration failed
+        let mut failed_date: Option<NaiveDate> = None;
+        loop {
+            thread::sleep(SCHEDULER_INTERVAL);
+            let next_scheduled = match state.get().next_scheduled() {
+                Some(date) => date,
+                None => continue,
+            };
+            if Local::now().date_naive() < next_scheduled || failed_date == Some(next_scheduled) {
+                continue;
+            }
+            match state.reload() {
+                Ok(()) => println!("Published posts scheduled for {}", next_scheduled),
+                Err(err) => {
+                    failed_date = Some(next_scheduled);
+                    eprintln!("Failed to publish scheduled posts, keeping the previous version!");
+                    eprintln!("Error: {}", err);
+                }
+            }
+        }
+    });
+}
+
+fn root_dirs(state: &ServerState) -> Vec<PathBuf> {
+    let mut root_dirs: Vec<PathBuf> = state
+        .all_sites()
+        .map(|site| absolute(&site.config.root_dir))
+        .collect();
+    root_dirs.sort();
+    root_dirs.dedup();
+    root_dirs
+}
+
+fn watch_root_dir(watcher: &mut RecommendedWatcher, root_dir: &PathBuf) -> Result<(), String> {
+    watcher
+        .watch(root_dir, RecursiveMode::Recursive)
+        .map_err(|err| format!("Failed to watch root dir '{}': {}", root_dir.display(), err))
+}
+
+// The watcher reports absolute paths
+fn absolute(path: &PathBuf) -> PathBuf {
+    if path.is_absolute() {
+        path.clone()
+    } else {
+        env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.clone())
+    }
+}
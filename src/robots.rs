@@ -0,0 +1,51 @@
+use crate::{
+    config::RobotsConfig,
+    file_server::{resolve, Route},
+    page::is_noindex_page,
+    state::Site,
+};
+use actix_web::{
+    http::header::{HeaderName, HeaderValue},
+    HttpResponse,
+};
+
+pub const ROBOTS_TXT: &str = "robots.txt";
+
+// Generates robots.txt from the config, with a reference to the sitemap
+pub fn generate_robots_txt(site: &Site, robots_cfg: &RobotsConfig) -> String {
+    let mut robots_txt = format!("User-agent: {}\n", robots_cfg.user_agent);
+    for path in &robots_cfg.allow {
+        robots_txt.push_str(&format!("Allow: {}\n", path));
+    }
+    for path in &robots_cfg.disallow {
+        robots_txt.push_str(&format!("Disallow: {}\n", path));
+    }
+    if robots_cfg.allow.is_empty() && robots_cfg.disallow.is_empty() {
+        // An empty disallow rule allows everything
+        robots_txt.push_str("Disallow:\n");
+    }
+    if let Some(sitemap_cfg) = &site.config.sitemap {
+        robots_txt.push_str(&format!(
+            "\nSitemap: {}/{}\n",
+            site.config.server_name,
+            sitemap_cfg.link.display()
+        ));
+    }
+    robots_txt
+}
+
+// Whether a route is excluded from indexing by the metadata of its feed item or page
+pub fn is_noindex(site: &Site, route: &str) -> bool {
+    match resolve(site, route) {
+        Route::Generated(path) => site.generator.is_noindex(&path),
+        Route::Rendered(relative_path) => is_noindex_page(&site.config, &relative_path),
+        _ => false,
+    }
+}
+
+pub fn set_noindex_header(response: &mut HttpResponse) {
+    response.headers_mut().insert(
+        HeaderName::from_static("x-robots-tag"),
+        HeaderValue::from_static("noindex"),
+    );
+}
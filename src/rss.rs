@@ -1,6 +1,6 @@
 use crate::{
-    config::{FeedConfig, FeedOutput},
-    generator::FeedItem,
+    config::FeedConfig,
+    generator::{FeedItem, FeedLinks},
 };
 use chrono::NaiveDate;
 use quick_xml::{se::Serializer, Writer};
@@ -48,32 +48,13 @@ pub fn date_to_rfc822(date: &NaiveDate) -> String {
 }
 
 pub fn generate_rss_xml(
-    feed_items: &Vec<FeedItem>,
+    feed_items: &[FeedItem],
     feed_cfg: &FeedConfig,
-    server_name: &String,
-    index_output: &FeedOutput,
-    feed_link: &String,
+    links: &FeedLinks,
 ) -> Result<String, String> {
-    let full_feed_link = server_name.clone() + "/" + feed_link;
-
-    let full_index_link = server_name.clone()
-        + "/"
-        + index_output
-            .link
-            .to_str()
-            .ok_or("Index link string conversion failed!")?;
-
     let mut rss_items = Vec::new();
     for feed_item in feed_items {
-        let link = {
-            if let Some(item_link) = &feed_item.link {
-                server_name.clone() + "/" + &item_link
-            } else {
-                let id_link = full_index_link.clone() + "#" + &feed_item.file_name;
-                id_link.clone()
-            }
-        };
-
+        let link = links.item(feed_item);
         rss_items.push(RssItem {
             title: feed_item.meta.title.clone(),
             link: link.clone(),
@@ -85,9 +66,9 @@ pub fn generate_rss_xml(
     let channel = RssChannel {
         title: feed_cfg.title.clone(),
         description: feed_cfg.description.clone(),
-        link: full_feed_link.to_string(),
+        link: links.feed.clone(),
         atom_link: RssLink {
-            href: full_feed_link.to_string(),
+            href: links.feed.clone(),
             rel: "self".to_string(),
             content_type: "application/rss+xml".to_string(),
         },
@@ -99,11 +80,11 @@ pub fn generate_rss_xml(
     let mut ser = Serializer::with_root(writer, Some("channel"));
     channel
         .serialize(&mut ser)
-        .map_err(|err| format!("XML Serialization error: {}", err.to_string()))?;
+        .map_err(|err| format!("XML Serialization error: {}", err))?;
     let rss_channel_str = String::from_utf8(buffer)
-        .map_err(|err| format!("Channel string conversion error: {}", err.to_string()))?;
-    return Ok(format!(
+        .map_err(|err| format!("Channel string conversion error: {}", err))?;
+    Ok(format!(
         "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">{}</rss>",
         rss_channel_str
-    ));
+    ))
 }
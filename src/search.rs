@@ -0,0 +1,320 @@
+use crate::{
+    config::SearchConfig,
+    file_server::{is_template, resolve, routes, Route},
+    robots::is_noindex,
+    state::Site,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const DEFAULT_MAX_RESULTS: usize = 20;
+// Number of words in a snippet, and before the first match
+const SNIPPET_WORDS: usize = 30;
+const SNIPPET_CONTEXT: usize = 10;
+// Weights of the fields of a document
+const TITLE_WEIGHT: u32 = 5;
+const TAG_WEIGHT: u32 = 3;
+const SUBTITLE_WEIGHT: u32 = 2;
+const TEXT_WEIGHT: u32 = 1;
+
+// A feed item or page in the search index
+#[derive(Serialize, Clone)]
+pub struct SearchDocument {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub tags: Vec<String>,
+    pub link: String,
+    // Body text without html tags
+    pub text: String,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    title: String,
+    subtitle: Option<String>,
+    tags: Vec<String>,
+    link: String,
+    // Html with the matching words in <mark> tags
+    snippet: String,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    // Number of hits, at most the configured maximum
+    pub limit: Option<usize>,
+}
+
+// Inverted index of words to the documents containing them and their weighted count
+#[derive(Serialize)]
+pub struct SearchIndex {
+    documents: Vec<SearchDocument>,
+    index: BTreeMap<String, Vec<(usize, u32)>>,
+    #[serde(skip)]
+    max_results: usize,
+}
+
+impl SearchIndex {
+    // Indexes the feed items of the generator and all rendered pages
+    pub fn build(site: &Site, search_cfg: &SearchConfig) -> Result<Self, String> {
+        let mut documents: Vec<SearchDocument> = site.generator.search_documents().to_vec();
+        for route in routes(site)? {
+            let relative_path = match resolve(site, &route) {
+                Route::Rendered(relative_path) => relative_path,
+                _ => continue,
+            };
+            if is_template(&site.config, &relative_path) || is_noindex(site, &route) {
+                continue;
+            }
+            let content = site.render_page(&relative_path)?;
+            documents.push(SearchDocument {
+                title: page_title(&content.body).unwrap_or_else(|| format!("/{}", route)),
+                subtitle: None,
+                tags: Vec::new(),
+                link: format!("/{}", route),
+                text: strip_html(&content.body),
+            });
+        }
+        Ok(Self::new(
+            documents,
+            search_cfg.max_results.unwrap_or(DEFAULT_MAX_RESULTS),
+        ))
+    }
+
+    fn new(documents: Vec<SearchDocument>, max_results: usize) -> Self {
+        let mut counts: HashMap<String, HashMap<usize, u32>> = HashMap::new();
+        for (id, document) in documents.iter().enumerate() {
+            let mut add = |text: &str, weight: u32| {
+                for token in tokenize(text) {
+                    *counts.entry(token).or_default().entry(id).or_default() += weight;
+                }
+            };
+            add(&document.title, TITLE_WEIGHT);
+            for tag in &document.tags {
+                add(tag, TAG_WEIGHT);
+            }
+            if let Some(subtitle) = &document.subtitle {
+                add(subtitle, SUBTITLE_WEIGHT);
+            }
+            add(&document.text, TEXT_WEIGHT);
+        }
+        let index = counts
+            .into_iter()
+            .map(|(token, docs)| {
+                let mut postings: Vec<(usize, u32)> = docs.into_iter().collect();
+                postings.sort();
+                (token, postings)
+            })
+            .collect();
+
+        Self {
+            documents,
+            index,
+            max_results,
+        }
+    }
+
+    // Documents containing all words of the query, the last word may be incomplete
+    pub fn search(&self, query: &str, limit: Option<usize>) -> Vec<SearchHit> {
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let total = self.documents.len() as f64;
+        let mut scores: HashMap<usize, (f64, usize)> = HashMap::new();
+        for term in &terms {
+            let mut term_counts: HashMap<usize, u32> = HashMap::new();
+            for (token, postings) in self.index.range(term.clone()..) {
+                if !token.starts_with(term.as_str()) {
+                    break;
+                }
+                for (id, count) in postings {
+                    *term_counts.entry(*id).or_default() += count;
+                }
+            }
+            // Rare words weigh more
+            let idf = (total / term_counts.len().max(1) as f64).ln() + 1.0;
+            for (id, count) in term_counts {
+                let score = scores.entry(id).or_default();
+                score.0 += count as f64 * idf;
+                score.1 += 1;
+            }
+        }
+
+        let mut hits: Vec<(usize, f64)> = scores
+            .into_iter()
+            .filter(|(_, (_, matched))| *matched == terms.len())
+            .map(|(id, (score, _))| (id, score))
+            .collect();
+        hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let limit = limit.map_or(self.max_results, |limit| limit.min(self.max_results));
+        hits.into_iter()
+            .take(limit)
+            .map(|(id, score)| {
+                let document = &self.documents[id];
+                SearchHit {
+                    title: document.title.clone(),
+                    subtitle: document.subtitle.clone(),
+                    tags: document.tags.clone(),
+                    link: document.link.clone(),
+                    snippet: snippet(&document.text, &terms),
+                    score,
+                }
+            })
+            .collect()
+    }
+
+    // The documents and the index for client-side search
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self)
+            .map_err(|err| format!("Search index serialization error: {}", err))
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+// Words around the first match, with all matches highlighted
+fn snippet(text: &str, terms: &[String]) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let matches = |word: &str| {
+        tokenize(word)
+            .iter()
+            .any(|token| terms.iter().any(|term| token.starts_with(term.as_str())))
+    };
+    let start = words
+        .iter()
+        .position(|word| matches(word))
+        .map(|i| i.saturating_sub(SNIPPET_CONTEXT))
+        .unwrap_or(0);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str("… ");
+    }
+    for (i, word) in words[start..end].iter().enumerate() {
+        if i > 0 {
+            snippet.push(' ');
+        }
+        if matches(word) {
+            snippet.push_str(&format!("<mark>{}</mark>", escape_html(word)));
+        } else {
+            snippet.push_str(&escape_html(word));
+        }
+    }
+    if end < words.len() {
+        snippet.push_str(" …");
+    }
+    snippet
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn page_title(html: &str) -> Option<String> {
+    let start = html.find("<title>")? + "<title>".len();
+    let end = html[start..].find("</title>")? + start;
+    let title = decode_entities(html[start..end].trim());
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+// Text content of html, without scripts and styles
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(i) = rest.find('<') {
+        text.push_str(&rest[..i]);
+        text.push(' ');
+        rest = &rest[i..];
+        let lower = rest
+            .get(..7)
+            .map(|start| start.to_lowercase())
+            .unwrap_or_default();
+        let skip_until = if lower.starts_with("<script") {
+            "</script>"
+        } else if lower.starts_with("<style") {
+            "</style>"
+        } else {
+            ">"
+        };
+        rest = match rest.find(skip_until) {
+            Some(end) => &rest[end + skip_until.len()..],
+            None => "",
+        };
+    }
+    text.push_str(rest);
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(title: &str, text: &str) -> SearchDocument {
+        SearchDocument {
+            title: title.to_string(),
+            subtitle: None,
+            tags: Vec::new(),
+            link: format!("/{}", title.to_lowercase()),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn limit_is_clamped_to_max_results() {
+        let documents = (0..5)
+            .map(|i| document(&format!("Post {}", i), "rust web server"))
+            .collect();
+        let index = SearchIndex::new(documents, 3);
+        assert_eq!(index.search("rust", None).len(), 3);
+        assert_eq!(index.search("rust", Some(1)).len(), 1);
+        assert_eq!(index.search("rust", Some(1000)).len(), 3);
+    }
+
+    #[test]
+    fn matches_all_terms_and_prefixes() {
+        let index = SearchIndex::new(
+            vec![
+                document("Rust", "A web server in rust"),
+                document("Go", "A web server in go"),
+            ],
+            DEFAULT_MAX_RESULTS,
+        );
+        let hits = index.search("web ru", None);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].link, "/rust");
+        assert_eq!(
+            hits[0].snippet,
+            "A <mark>web</mark> server in <mark>rust</mark>"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{
+    config::SitemapConfig,
+    file_server::{is_template, resolve, routes, Route},
+    robots::is_noindex,
+    state::Site,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use quick_xml::{se::Serializer, Writer};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const SITEMAP_XMLNS: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+// Maximum number of urls in a single sitemap file
+const MAX_SITEMAP_URLS: usize = 50000;
+// Characters which aren't allowed in the path of an url, the XML serializer escapes '&'
+const PATH_ESCAPE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+#[derive(Serialize)]
+struct UrlSet {
+    xmlns: String,
+    #[serde(rename = "url")]
+    urls: Vec<SitemapUrl>,
+}
+
+#[derive(Serialize)]
+struct SitemapUrl {
+    #[serde(rename = "$unflatten=loc")]
+    loc: String,
+    #[serde(rename = "$unflatten=lastmod", skip_serializing_if = "Option::is_none")]
+    lastmod: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SitemapIndex {
+    xmlns: String,
+    #[serde(rename = "sitemap")]
+    sitemaps: Vec<SitemapUrl>,
+}
+
+// Generates the sitemap files of a site, the sitemap is split into parts behind
+// a sitemap index when it has too many urls
+pub fn generate_sitemap(
+    site: &Site,
+    sitemap_cfg: &SitemapConfig,
+) -> Result<Vec<(PathBuf, String)>, String> {
+    let config = &site.config;
+
+    let mut urls = Vec::new();
+    for route in routes(site)? {
+        if is_noindex(site, &route) {
+            continue;
+        }
+        let lastmod = match resolve(site, &route) {
+            Route::Rendered(relative_path) => {
+                // Templates are content files as well, but aren't pages
+                if is_template(config, &relative_path) {
+                    continue;
+                }
+                // Pages change with their source file
+                fs::metadata(config.root_dir.join(&relative_path))
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .map(|modified| DateTime::<Utc>::from(modified).date_naive())
+            }
+            Route::Generated(path) => site.generator.last_modified(&path),
+            _ => continue,
+        };
+        urls.push(SitemapUrl {
+            loc: url(&config.server_name, &route),
+            lastmod: lastmod.as_ref().map(w3c_date),
+        });
+    }
+
+    if urls.len() <= MAX_SITEMAP_URLS {
+        let url_set = UrlSet {
+            xmlns: SITEMAP_XMLNS.to_string(),
+            urls,
+        };
+        return Ok(vec![(
+            sitemap_cfg.link.clone(),
+            to_xml(&url_set, "urlset")?,
+        )]);
+    }
+
+    let mut files = Vec::new();
+    let mut sitemaps = Vec::new();
+    let mut urls = urls.into_iter().peekable();
+    while urls.peek().is_some() {
+        let part_urls: Vec<SitemapUrl> = urls.by_ref().take(MAX_SITEMAP_URLS).collect();
+        let part_link = part_link(&sitemap_cfg.link, files.len() + 1);
+        sitemaps.push(SitemapUrl {
+            loc: url(&config.server_name, &part_link.to_string_lossy()),
+            lastmod: None,
+        });
+        let url_set = UrlSet {
+            xmlns: SITEMAP_XMLNS.to_string(),
+            urls: part_urls,
+        };
+        files.push((part_link, to_xml(&url_set, "urlset")?));
+    }
+    let index = SitemapIndex {
+        xmlns: SITEMAP_XMLNS.to_string(),
+        sitemaps,
+    };
+    files.push((sitemap_cfg.link.clone(), to_xml(&index, "sitemapindex")?));
+    Ok(files)
+}
+
+// Link of a part of the sitemap, e.g. sitemap-1.xml
+fn part_link(link: &Path, n: usize) -> PathBuf {
+    let stem = link
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    link.with_file_name(format!("{}-{}.xml", stem, n))
+}
+
+fn url(server_name: &str, route: &str) -> String {
+    format!(
+        "{}/{}",
+        server_name,
+        utf8_percent_encode(route, PATH_ESCAPE)
+    )
+}
+
+fn w3c_date(date: &NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+fn to_xml<T: Serialize>(value: &T, root: &str) -> Result<String, String> {
+    let mut buffer = Vec::new();
+    let writer = Writer::new_with_indent(&mut buffer, b' ', 2);
+    let mut ser = Serializer::with_root(writer, Some(root));
+    value
+        .serialize(&mut ser)
+        .map_err(|err| format!("XML Serialization error: {}", err))?;
+    let xml = String::from_utf8(buffer)
+        .map_err(|err| format!("Sitemap string conversion error: {}", err))?;
+    Ok(format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>{}", xml))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_loc() {
+        let url_set = UrlSet {
+            xmlns: SITEMAP_XMLNS.to_string(),
+            urls: vec![SitemapUrl {
+                loc: url("https://example.com", "a b&c/ü"),
+                lastmod: Some(w3c_date(&NaiveDate::from_ymd_opt(2021, 2, 3).unwrap())),
+            }],
+        };
+        let xml = to_xml(&url_set, "urlset").unwrap();
+        assert!(xml.contains("<loc>https://example.com/a%20b&amp;c/%C3%BC</loc>"));
+        assert!(xml.contains("<lastmod>2021-02-03</lastmod>"));
+    }
+
+    #[test]
+    fn part_links() {
+        assert_eq!(
+            part_link(&PathBuf::from("maps/sitemap.xml"), 2),
+            PathBuf::from("maps/sitemap-2.xml")
+        );
+    }
+}
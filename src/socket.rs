@@ -0,0 +1,323 @@
+use crate::config::ServerConfig;
+use actix_web::dev::Server;
+use std::{
+    io,
+    net::{IpAddr, TcpListener},
+};
+
+#[cfg(unix)]
+use std::{
+    env,
+    fs::File,
+    io::{Read, Write},
+    os::unix::{
+        io::{AsRawFd, FromRawFd, RawFd},
+        process::CommandExt,
+    },
+    process::{self, Child, Command},
+    time::Duration,
+};
+
+// First file descriptor passed with LISTEN_FDS
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+// Names of the sockets in LISTEN_FDNAMES, also used with systemd's FileDescriptorName=
+const HTTP_SOCKET: &str = "http";
+const HTTPS_SOCKET: &str = "https";
+const METRICS_SOCKET: &str = "metrics";
+// Pipe the new process writes to once it is serving
+#[cfg(unix)]
+const READY_FD_VAR: &str = "WEBSERVER_READY_FD";
+// Time the new process gets to load the sites and start serving
+#[cfg(unix)]
+const READY_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Listening sockets, bound by the server or inherited from systemd or a previous process
+pub struct Listeners {
+    pub http: TcpListener,
+    pub https: Option<TcpListener>,
+    pub metrics: Option<TcpListener>,
+}
+
+impl Listeners {
+    // Inherited sockets are matched by their name in LISTEN_FDNAMES. Sockets without one of
+    // our names, e.g. systemd's default of the unit name, are used in order: http, https,
+    // metrics. Other sockets are bound.
+    pub fn open(config: &ServerConfig) -> io::Result<Self> {
+        let mut inherited = inherited_listeners();
+        let mut open = |name: &str, address: IpAddr, port: u16| {
+            let index = inherited
+                .iter()
+                .position(|(fd_name, _)| fd_name == name)
+                .or_else(|| {
+                    inherited.iter().position(|(fd_name, _)| {
+                        ![HTTP_SOCKET, HTTPS_SOCKET, METRICS_SOCKET].contains(&fd_name.as_str())
+                    })
+                });
+            match index {
+                Some(index) => Ok(inherited.remove(index).1),
+                None => TcpListener::bind((address, port)),
+            }
+        };
+        let http = open(HTTP_SOCKET, config.address, config.port)?;
+        let https = match &config.tls {
+            Some(tls) => Some(open(HTTPS_SOCKET, config.address, tls.port)?),
+            None => None,
+        };
+        let metrics = match &config.metrics {
+            Some(metrics) => Some(open(METRICS_SOCKET, metrics.address, metrics.port)?),
+            None => None,
+        };
+        for (name, listener) in &inherited {
+            match listener.local_addr() {
+                Ok(addr) => eprintln!("Ignoring inherited socket '{}' on {}", name, addr),
+                Err(_) => eprintln!("Ignoring inherited socket '{}'", name),
+            }
+        }
+        Ok(Self {
+            http,
+            https,
+            metrics,
+        })
+    }
+
+    #[cfg(unix)]
+    fn named_fds(&self) -> Vec<(&'static str, RawFd)> {
+        let mut fds = vec![(HTTP_SOCKET, self.http.as_raw_fd())];
+        if let Some(https) = &self.https {
+            fds.push((HTTPS_SOCKET, https.as_raw_fd()));
+        }
+        if let Some(metrics) = &self.metrics {
+            fds.push((METRICS_SOCKET, metrics.as_raw_fd()));
+        }
+        fds
+    }
+}
+
+// Takes the sockets passed with LISTEN_FDS in order with their names from LISTEN_FDNAMES,
+// which may be missing. Systemd also sets LISTEN_PID to our pid while the restart handoff
+// doesn't set it
+#[cfg(unix)]
+fn inherited_listeners() -> Vec<(String, TcpListener)> {
+    let count = match env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<RawFd>().ok())
+    {
+        Some(count) => count,
+        None => return Vec::new(),
+    };
+    if let Ok(pid) = env::var("LISTEN_PID") {
+        if pid.parse::<u32>().ok() != Some(process::id()) {
+            return Vec::new();
+        }
+    }
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDNAMES");
+
+    let mut names = names.split(':');
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            let listener = unsafe {
+                // Don't leak the sockets into other child processes
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                TcpListener::from_raw_fd(fd)
+            };
+            (names.next().unwrap_or_default().to_string(), listener)
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn inherited_listeners() -> Vec<(String, TcpListener)> {
+    Vec::new()
+}
+
+// Tells the previous process that we are serving, so it can stop
+#[cfg(unix)]
+pub fn notify_ready() {
+    let fd = match env::var(READY_FD_VAR)
+        .ok()
+        .and_then(|fd| fd.parse::<RawFd>().ok())
+    {
+        Some(fd) => fd,
+        None => return,
+    };
+    env::remove_var(READY_FD_VAR);
+    let mut ready = unsafe { File::from_raw_fd(fd) };
+    if let Err(err) = ready.write_all(b"1") {
+        eprintln!("Failed to notify the previous process: {}", err);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn notify_ready() {}
+
+// On SIGUSR2, starts the current binary with the listening sockets and stops this process
+// gracefully once the new one is serving
+#[cfg(unix)]
+pub fn spawn_restart_handler(listeners: &Listeners, servers: Vec<Server>) {
+    use actix_web::{
+        error::BlockingError,
+        rt::signal::unix::{signal, SignalKind},
+        web,
+    };
+
+    let fds = listeners.named_fds();
+    actix_web::rt::spawn(async move {
+        let mut restart_signal = match signal(SignalKind::user_defined2()) {
+            Ok(restart_signal) => restart_signal,
+            Err(err) => {
+                eprintln!(
+                    "Failed to listen for SIGUSR2, restarts are disabled: {}",
+                    err
+                );
+                return;
+            }
+        };
+        while restart_signal.recv().await.is_some() {
+            let restart = match reexec(&fds) {
+                Ok(restart) => restart,
+                Err(err) => {
+                    eprintln!("Failed to restart: {}", err);
+                    continue;
+                }
+            };
+            let pid = restart.child.id();
+            println!("Started new process {}, waiting until it is ready", pid);
+            let ready = web::block(move || restart.wait_ready())
+                .await
+                .map_err(|err| match err {
+                    BlockingError::Error(err) => err,
+                    BlockingError::Canceled => {
+                        "Waiting for the new process was canceled".to_string()
+                    }
+                });
+            match ready {
+                Ok(()) => {
+                    println!("New process {} is ready, stopping gracefully", pid);
+                    for server in &servers {
+                        server.stop(true).await;
+                    }
+                    return;
+                }
+                Err(err) => eprintln!("Failed to restart, keeping this process: {}", err),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_restart_handler(_listeners: &Listeners, _servers: Vec<Server>) {}
+
+// New process started on restart
+#[cfg(unix)]
+struct Restart {
+    child: Child,
+    // Read end of the ready pipe
+    ready: File,
+}
+
+#[cfg(unix)]
+impl Restart {
+    // Waits until the new process is serving, it is killed if it doesn't get ready in time
+    fn wait_ready(mut self) -> Result<(), String> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.ready.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let polled = loop {
+            let polled = unsafe { libc::poll(&mut poll_fd, 1, READY_TIMEOUT.as_millis() as i32) };
+            if polled >= 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                break polled;
+            }
+        };
+        if polled <= 0 {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            return Err("New process didn't get ready in time".to_string());
+        }
+
+        let mut buf = [0u8; 1];
+        match self.ready.read(&mut buf) {
+            Ok(1) => Ok(()),
+            // The write end is closed without a write when the process exits
+            _ => match self.child.wait() {
+                Ok(status) => Err(format!(
+                    "New process exited before it was ready ({})",
+                    status
+                )),
+                Err(err) => Err(format!("New process isn't ready: {}", err)),
+            },
+        }
+    }
+}
+
+// Spawns the current binary with the same arguments, the sockets become fds 3, 4, ...
+// followed by the write end of the ready pipe
+#[cfg(unix)]
+fn reexec(named_fds: &[(&str, RawFd)]) -> io::Result<Restart> {
+    let (ready_read, ready_write) = pipe()?;
+
+    let mut fds: Vec<RawFd> = named_fds.iter().map(|(_, fd)| *fd).collect();
+    fds.push(ready_write.as_raw_fd());
+    let names: Vec<&str> = named_fds.iter().map(|(name, _)| *name).collect();
+    // Temporary fds above both the sockets and the target range, so nothing is overwritten
+    let temp_start = fds
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(LISTEN_FDS_START + fds.len() as RawFd)
+        + 1;
+
+    let mut command = Command::new(env::current_exe()?);
+    command
+        .args(env::args_os().skip(1))
+        .env("LISTEN_FDS", named_fds.len().to_string())
+        .env("LISTEN_FDNAMES", names.join(":"))
+        .env(
+            READY_FD_VAR,
+            (LISTEN_FDS_START + named_fds.len() as RawFd).to_string(),
+        )
+        .env_remove("LISTEN_PID");
+    unsafe {
+        command.pre_exec(move || {
+            for (i, fd) in fds.iter().enumerate() {
+                if libc::dup2(*fd, temp_start + i as RawFd) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            // dup2 clears FD_CLOEXEC, so the sockets stay open across exec
+            for i in 0..fds.len() as RawFd {
+                if libc::dup2(temp_start + i, LISTEN_FDS_START + i) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                libc::close(temp_start + i);
+            }
+            Ok(())
+        });
+    }
+    let child = command.spawn()?;
+    // Only the new process may hold the write end, so its exit closes the pipe
+    drop(ready_write);
+    Ok(Restart {
+        child,
+        ready: ready_read,
+    })
+}
+
+// Pipe which isn't inherited by child processes
+#[cfg(unix)]
+fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0 as RawFd; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    for fd in &fds {
+        unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
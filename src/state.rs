@@ -0,0 +1,275 @@
+use crate::{
+    config::{ConfigOverrides, ServerConfig, SiteConfig},
+    content_cache::{CachedContent, RenderCache},
+    generator::Generator,
+    headers::HeaderRules,
+    link_checker, metrics,
+    page::{render_markdown_page, MARKDOWN_EXT},
+    robots::{generate_robots_txt, ROBOTS_TXT},
+    search::SearchIndex,
+    sitemap::generate_sitemap,
+    template_engine::TemplateEngine,
+};
+use chrono::NaiveDate;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, RwLock},
+};
+use tera::Context;
+
+pub struct Site {
+    pub config: SiteConfig,
+    pub template_engine: TemplateEngine,
+    pub generator: Generator,
+    pub render_cache: RenderCache,
+    // Built on first use, rendering every page is too slow for each reload
+    search_index: OnceLock<Result<SearchIndex, String>>,
+}
+
+impl Site {
+    pub fn load(config: SiteConfig, drafts: bool) -> Result<Self, String> {
+        let mut template_engine = TemplateEngine::load(&config).map_err(|err| {
+            format!(
+                "Failed to load template engine of '{}'!\n{}",
+                config.server_name, err
+            )
+        })?;
+        let generator =
+            Generator::generate(&config, drafts, &mut template_engine).map_err(|err| {
+                format!(
+                    "Failed to generate feeds of '{}'!\n{}",
+                    config.server_name, err
+                )
+            })?;
+        let mut site = Self {
+            config,
+            template_engine,
+            generator,
+            render_cache: RenderCache::new(),
+            search_index: OnceLock::new(),
+        };
+
+        // The sitemap is generated from the routes of the site
+        if let Some(sitemap_cfg) = site.config.sitemap.clone() {
+            let files = generate_sitemap(&site, &sitemap_cfg).map_err(|err| {
+                format!(
+                    "Failed to generate sitemap of '{}'!\n{}",
+                    site.config.server_name, err
+                )
+            })?;
+            for (path, content) in files {
+                site.generator.insert(path, content);
+            }
+        }
+        if let Some(robots_cfg) = &site.config.robots {
+            if !site.config.root_dir.join(ROBOTS_TXT).is_file() {
+                let robots_txt = generate_robots_txt(&site, robots_cfg);
+                site.generator.insert(PathBuf::from(ROBOTS_TXT), robots_txt);
+            }
+        }
+        Ok(site)
+    }
+
+    // Search index of the site, None if search isn't configured
+    pub fn search_index(&self) -> Result<Option<&SearchIndex>, String> {
+        let search_cfg = match &self.config.search {
+            Some(search_cfg) => search_cfg,
+            None => return Ok(None),
+        };
+        match self.search_index.get_or_init(|| {
+            SearchIndex::build(self, search_cfg).map_err(|err| {
+                format!(
+                    "Failed to build search index of '{}'!\n{}",
+                    self.config.server_name, err
+                )
+            })
+        }) {
+            Ok(search_index) => Ok(Some(search_index)),
+            Err(err) => Err(err.clone()),
+        }
+    }
+
+    // Keeps the unchanged content of the previous version of the site
+    fn keep_unchanged(&mut self, previous: &Site) {
+        self.generator.keep_unchanged(&previous.generator);
+        self.render_cache.keep_unchanged(&previous.render_cache);
+    }
+
+    // Renders a template or markdown page relative to the root dir, cached per generation
+    pub fn render_page(&self, relative_path: &PathBuf) -> Result<Arc<CachedContent>, String> {
+        self.render_cache.get_or_render(relative_path, || {
+            if relative_path
+                .extension()
+                .is_some_and(|ext| ext == MARKDOWN_EXT)
+            {
+                let (page, noindex) = render_markdown_page(self, relative_path)?;
+                Ok(CachedContent::new(page).with_noindex(noindex))
+            } else {
+                self.template_engine
+                    .render_file(relative_path.clone(), &Context::new())
+                    .map(CachedContent::new)
+            }
+        })
+    }
+}
+
+pub struct ServerState {
+    pub config: ServerConfig,
+    pub default_site: Site,
+    pub sites: Vec<Site>,
+    pub header_rules: HeaderRules,
+}
+
+impl ServerState {
+    pub fn load(config_path: &Path, overrides: &ConfigOverrides) -> Result<Self, String> {
+        let config = ServerConfig::load(config_path.to_path_buf(), overrides)?;
+        let header_rules = HeaderRules::new(&config.headers)?;
+        let default_site = Site::load(config.site.clone(), config.drafts)?;
+        let mut sites = Vec::new();
+        for site_config in &config.sites {
+            sites.push(Site::load(site_config.clone(), config.drafts)?);
+        }
+        // Only the live state is reported
+        metrics::reset_feed_items();
+        for site in std::iter::once(&default_site).chain(sites.iter()) {
+            for (feed, count) in site.generator.feed_counts() {
+                metrics::feed_items(&site.config.server_name, feed, *count);
+            }
+        }
+        Ok(Self {
+            config,
+            default_site,
+            sites,
+            header_rules,
+        })
+    }
+
+    // Returns the site matching the host, or the default site
+    pub fn site(&self, host: &str) -> &Site {
+        self.sites
+            .iter()
+            .find(|site| matches_host(&site.config.hosts, host))
+            .unwrap_or(&self.default_site)
+    }
+
+    fn keep_unchanged(&mut self, previous: &ServerState) {
+        let sites = std::iter::once(&mut self.default_site).chain(self.sites.iter_mut());
+        for site in sites {
+            let previous_site = previous
+                .all_sites()
+                .find(|previous_site| previous_site.config.server_name == site.config.server_name);
+            if let Some(previous_site) = previous_site {
+                site.keep_unchanged(previous_site);
+            }
+        }
+    }
+
+    pub fn all_sites(&self) -> impl Iterator<Item = &Site> {
+        std::iter::once(&self.default_site).chain(self.sites.iter())
+    }
+
+    // Crawls all sites when the link check is configured, renders every page
+    pub fn check_links(&self) -> Result<(), String> {
+        match &self.config.link_check {
+            Some(link_check) => {
+                link_checker::run(link_check, &self.all_sites().collect::<Vec<_>>())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Date of the first scheduled post of all sites
+    pub fn next_scheduled(&self) -> Option<NaiveDate> {
+        self.all_sites()
+            .filter_map(|site| site.generator.next_scheduled())
+            .min()
+    }
+}
+
+// Whether the Host header matches one of the hosts, ignoring case and port
+pub fn matches_host(hosts: &[String], host: &str) -> bool {
+    let host = strip_port(host).to_ascii_lowercase();
+    hosts
+        .iter()
+        .any(|site_host| site_host.to_ascii_lowercase() == host)
+}
+
+// Strips the port from a host, e.g. 'example.com:8080' or '[::1]:8080'
+pub fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(i) if !host.ends_with(']') && (host.starts_with('[') || !host[..i].contains(':')) => {
+            &host[..i]
+        }
+        _ => host,
+    }
+}
+
+// Holds the current state, which can be swapped out while the server is running
+pub struct SharedState {
+    config_path: PathBuf,
+    overrides: ConfigOverrides,
+    current: RwLock<Arc<ServerState>>,
+    // The watcher and the scheduler reload from their own threads, one at a time so an
+    // older load can't replace a newer one
+    reloading: Mutex<()>,
+}
+
+impl SharedState {
+    pub fn new(config_path: PathBuf, overrides: ConfigOverrides, state: ServerState) -> Self {
+        Self {
+            config_path,
+            overrides,
+            current: RwLock::new(Arc::new(state)),
+            reloading: Mutex::new(()),
+        }
+    }
+
+    pub fn config_path(&self) -> &PathBuf {
+        &self.config_path
+    }
+
+    pub fn get(&self) -> Arc<ServerState> {
+        self.current
+            .read()
+            .expect("Server state lock is poisoned!")
+            .clone()
+    }
+
+    // Rebuilds the state from disk, the current state stays live if this fails
+    pub fn reload(&self) -> Result<(), String> {
+        let _reloading = self
+            .reloading
+            .lock()
+            .expect("Server reload lock is poisoned!");
+        let mut state = ServerState::load(&self.config_path, &self.overrides)?;
+        state.keep_unchanged(&self.get());
+        *self
+            .current
+            .write()
+            .expect("Server state lock is poisoned!") = Arc::new(state);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_port() {
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("example.com:8443"), "example.com");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+    }
+
+    #[test]
+    fn matches_host_ignoring_case_and_port() {
+        let hosts = vec!["example.com".to_string(), "Blog.Example.com".to_string()];
+        assert!(matches_host(&hosts, "example.com"));
+        assert!(matches_host(&hosts, "Example.COM"));
+        assert!(matches_host(&hosts, "example.com:8443"));
+        assert!(matches_host(&hosts, "blog.example.com"));
+        assert!(!matches_host(&hosts, "other.example.com"));
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use crate::config::ServerConfig;
+use crate::config::SiteConfig;
 use tera::{Context, Tera};
 
 #[derive(Clone)]
@@ -9,7 +9,7 @@ pub struct TemplateEngine {
 }
 
 impl TemplateEngine {
-    pub fn load(config: &ServerConfig) -> Result<Self, String> {
+    pub fn load(config: &SiteConfig) -> Result<Self, String> {
         let dirs = format!(
             "{}/**/*.{}",
             config.root_dir.to_string_lossy(),
@@ -27,16 +27,11 @@ impl TemplateEngine {
         Ok(Self { tera })
     }
 
-    // TODO: Add a way to reload
-    pub fn _reload(&mut self) -> Result<(), String> {
-        self.tera.full_reload().map_err(|err| err.to_string())
-    }
-
     pub fn render_file(&self, path: PathBuf, context: &Context) -> Result<String, String> {
         if let Some(path_str) = path.to_str() {
             self.tera
                 .render(path_str, context)
-                .map_err(|err| format!("Template rendering error (File): {}", err.to_string()))
+                .map_err(|err| format!("Template rendering error (File): {}", err))
         } else {
             Err("Failed to convert path to string!".to_string())
         }
@@ -45,6 +40,6 @@ impl TemplateEngine {
     pub fn render_string(&mut self, template: &str, context: &Context) -> Result<String, String> {
         self.tera
             .render_str(template, context)
-            .map_err(|err| format!("Template rendering error (String): {}", err.to_string()))
+            .map_err(|err| format!("Template rendering error (String): {}", err))
     }
 }
@@ -0,0 +1,64 @@
+// Sites in temp dirs, shared by the tests of all modules
+use crate::{
+    config::{FeedConfig, SiteConfig},
+    generator::FeedItem,
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+// Site config with only the required fields, tests set the others
+pub fn site_config(root_dir: &Path) -> SiteConfig {
+    serde_yaml::from_str(&format!(
+        "server_name: https://example.com
+root_dir: {}
+index: index.html
+content_ext: html
+ignored_paths: []
+feeds: []
+permalinks: {{}}",
+        root_dir.display()
+    ))
+    .unwrap()
+}
+
+// Feed config from yaml, title, description and source dir are required
+pub fn feed_config(yaml: &str) -> FeedConfig {
+    serde_yaml::from_str(yaml).unwrap()
+}
+
+// Feed item with the front matter fields in yaml and its html content
+pub fn feed_item(file_name: &str, meta_yaml: &str, content: &str) -> FeedItem {
+    FeedItem::new(
+        file_name.to_string(),
+        serde_yaml::from_str(meta_yaml).unwrap(),
+        content.to_string(),
+    )
+}
+
+// Markdown post with the given front matter fields after title and date
+pub fn post(title: &str, date: &str, fields: &str) -> String {
+    format!(
+        "---\ntitle: {}\ndate: {}\ncontent_type: md\n{}---\n{} text\n",
+        title, date, fields, title
+    )
+}
+
+// Empty root dir of a test, the name must be unique across tests
+pub fn root_dir(name: &str) -> PathBuf {
+    let root_dir = env::temp_dir().join(format!("webserver-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&root_dir);
+    fs::create_dir_all(&root_dir).unwrap();
+    root_dir
+}
+
+// Writes files relative to the root dir, creating their dirs
+pub fn write_files(root_dir: &Path, files: &[(&str, &str)]) {
+    for (path, content) in files {
+        let path = root_dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{config::TlsConfig, state::strip_port};
+use actix_files::NamedFile;
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderValue, LOCATION, STRICT_TRANSPORT_SECURITY},
+    Error, HttpResponse,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use rustls::{
+    internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys},
+    sign::{any_supported_type, CertifiedKey},
+    ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig as RustlsConfig,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+};
+
+pub const ACME_CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+// Holds the current certificate, which can be swapped without restarting the listener
+pub struct CertResolver {
+    key: RwLock<Option<CertifiedKey>>,
+}
+
+impl CertResolver {
+    pub fn new() -> Self {
+        Self {
+            key: RwLock::new(None),
+        }
+    }
+
+    pub fn set(&self, key: CertifiedKey) {
+        *self.key.write().expect("Certificate lock is poisoned!") = Some(key);
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        self.key
+            .read()
+            .expect("Certificate lock is poisoned!")
+            .clone()
+    }
+}
+
+// Parses a PEM certificate chain and a PEM (PKCS8 or RSA) private key
+pub fn certified_key(cert_pem: &[u8], key_pem: &[u8]) -> Result<CertifiedKey, String> {
+    let cert_chain = certs(&mut &cert_pem[..]).map_err(|_| "Invalid certificate!".to_string())?;
+    if cert_chain.is_empty() {
+        return Err("No certificates found!".to_string());
+    }
+
+    let mut keys = pkcs8_private_keys(&mut &key_pem[..]).unwrap_or_default();
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut &key_pem[..]).unwrap_or_default();
+    }
+    let key = keys.first().ok_or("No private key found!".to_string())?;
+    let signing_key =
+        any_supported_type(key).map_err(|_| "Unsupported private key type!".to_string())?;
+
+    Ok(CertifiedKey::new(cert_chain, Arc::new(signing_key)))
+}
+
+// Creates the rustls config, the certificate is loaded from the configured files
+// or provided later by ACME
+pub fn load_rustls_config(tls: &TlsConfig) -> Result<(RustlsConfig, Arc<CertResolver>), String> {
+    let resolver = Arc::new(CertResolver::new());
+    match (&tls.cert, &tls.key) {
+        (Some(cert), Some(key)) => {
+            let cert_pem = fs::read(cert).map_err(|err| {
+                format!(
+                    "Failed to read certificate file '{}': {}",
+                    cert.display(),
+                    err
+                )
+            })?;
+            let key_pem = fs::read(key)
+                .map_err(|err| format!("Failed to read key file '{}': {}", key.display(), err))?;
+            resolver.set(certified_key(&cert_pem, &key_pem).map_err(|err| {
+                format!(
+                    "Invalid certificate '{}' or key '{}': {}",
+                    cert.display(),
+                    key.display(),
+                    err
+                )
+            })?);
+        }
+        (None, None) if tls.acme.is_some() => {}
+        _ => {
+            return Err(
+                "Both a certificate and key file or an ACME config are required for TLS!"
+                    .to_string(),
+            )
+        }
+    }
+
+    let mut config = RustlsConfig::new(NoClientAuth::new());
+    config.cert_resolver = resolver.clone();
+    Ok((config, resolver))
+}
+
+// Directory to serve ACME challenges from
+pub fn acme_challenge_dir(tls: &TlsConfig, root_dir: &Path) -> PathBuf {
+    match (&tls.acme_challenge_dir, &tls.acme) {
+        (Some(dir), _) => dir.clone(),
+        (None, Some(acme)) => acme.state_dir.join("acme-challenge"),
+        (None, None) => root_dir.join(&ACME_CHALLENGE_PATH[1..]),
+    }
+}
+
+// Strict-Transport-Security header of the config, if HSTS is enabled
+pub fn hsts_header(tls: &TlsConfig) -> Result<Option<HeaderValue>, String> {
+    let max_age = match tls.hsts_max_age {
+        Some(max_age) => max_age,
+        None => return Ok(None),
+    };
+    let value = if tls.hsts_include_subdomains {
+        format!("max-age={}; includeSubDomains", max_age)
+    } else {
+        format!("max-age={}", max_age)
+    };
+    HeaderValue::from_str(&value)
+        .map(Some)
+        .map_err(|err| format!("Invalid HSTS header '{}': {}", value, err))
+}
+
+// Redirects plain HTTP requests to HTTPS, except for ACME challenges,
+// and adds the HSTS header to HTTPS responses
+// Does nothing if TLS is not configured
+pub struct HttpsRedirect {
+    enabled: bool,
+    https_port: u16,
+    acme_challenge_dir: PathBuf,
+    hsts: Option<HeaderValue>,
+}
+
+impl HttpsRedirect {
+    pub fn new(tls: Option<&TlsConfig>, root_dir: &Path, hsts: Option<HeaderValue>) -> Self {
+        let tls = match tls {
+            Some(tls) => tls,
+            None => {
+                return Self {
+                    enabled: false,
+                    https_port: 0,
+                    acme_challenge_dir: root_dir.to_path_buf(),
+                    hsts: None,
+                }
+            }
+        };
+        Self {
+            enabled: true,
+            https_port: tls.port,
+            acme_challenge_dir: acme_challenge_dir(tls, root_dir),
+            hsts,
+        }
+    }
+}
+
+impl<S, B> Transform<S> for HttpsRedirect
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = HttpsRedirectMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(HttpsRedirectMiddleware {
+            service,
+            enabled: self.enabled,
+            https_port: self.https_port,
+            acme_challenge_dir: self.acme_challenge_dir.clone(),
+            hsts: self.hsts.clone(),
+        })
+    }
+}
+
+pub struct HttpsRedirectMiddleware<S> {
+    service: S,
+    enabled: bool,
+    https_port: u16,
+    acme_challenge_dir: PathBuf,
+    hsts: Option<HeaderValue>,
+}
+
+impl<S, B> Service for HttpsRedirectMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        if !self.enabled {
+            return Box::pin(self.service.call(req));
+        }
+        if req.app_config().secure() {
+            let hsts = self.hsts.clone();
+            let fut = self.service.call(req);
+            return Box::pin(async move {
+                let mut res = fut.await?;
+                if let Some(hsts) = hsts {
+                    res.headers_mut().insert(STRICT_TRANSPORT_SECURITY, hsts);
+                }
+                Ok(res)
+            });
+        }
+
+        // Serve ACME HTTP-01 challenges from disk for certificate renewal
+        if let Some(token) = req.path().strip_prefix(ACME_CHALLENGE_PATH) {
+            let path = if !token.is_empty() && !token.contains('/') && !token.contains("..") {
+                Some(self.acme_challenge_dir.join(token))
+            } else {
+                None
+            };
+            let (http_req, _) = req.into_parts();
+            let response = match path.map(NamedFile::open) {
+                Some(Ok(file)) => file
+                    .into_response(&http_req)
+                    .unwrap_or_else(|_| HttpResponse::BadRequest().finish()),
+                _ => HttpResponse::NotFound().finish(),
+            };
+            return Box::pin(ok(ServiceResponse::new(http_req, response.into_body())));
+        }
+
+        let location = {
+            let connection_info = req.connection_info();
+            // Strip the port of the plain HTTP listener
+            let host = strip_port(connection_info.host());
+            let path = req
+                .uri()
+                .path_and_query()
+                .map(|path| path.as_str())
+                .unwrap_or("/");
+            if self.https_port == 443 {
+                format!("https://{}{}", host, path)
+            } else {
+                format!("https://{}:{}{}", host, self.https_port, path)
+            }
+        };
+        let response = HttpResponse::MovedPermanently()
+            .set_header(LOCATION, location)
+            .finish();
+        Box::pin(ok(req.into_response(response.into_body())))
+    }
+}